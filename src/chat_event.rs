@@ -1,24 +1,63 @@
 use std::fmt;
 
-use ed25519_dalek::ed25519::signature::Signer;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use ed25519_dalek::ed25519::signature::Signer as _;
 use ed25519_dalek::{Signature, SignatureError as DalekError, SigningKey, VerifyingKey};
 use iroh::NodeId;
+use iroh_blobs::Hash;
+use iroh_gossip::proto::TopicId;
 use owo_colors::OwoColorize;
 use palette::{FromColor as _, Hsl, Srgb};
 use postcard::Error as PostcardError;
 use serde::{Deserialize, Serialize};
 use thiserror::Error as ThisError;
 
+use crate::preview::MAX_THUMBNAIL_BYTES;
+use crate::{markdown, sanitize};
+
 type Nonce = [u8; 16];
 
+/// Versão do formato de `SignedChatEvent` e do que entra na assinatura.
+const VERSION: u32 = 1;
+
+/// Começo de tudo que assinamos como evento, para a assinatura não servir em
+/// outro protocolo que use a mesma chave (o iroh, a descoberta).
+const SIGNING_TAG: &[u8] = b"chat-p2p event v1";
+
+/// Tamanho máximo, em bytes, do nome de uma pessoa.
+pub const MAX_NAME_BYTES: usize = 64;
+
+/// Tamanho máximo, em bytes, do nome de um arquivo compartilhado.
+pub const MAX_FILE_NAME_BYTES: usize = 255;
+
+/// Tamanho máximo, em bytes, do texto de uma mensagem ou edição.
+pub const MAX_TEXT_BYTES: usize = 2048;
+
+/// Quantas pontas do log uma operação de sala pode citar.
+pub const MAX_PARENTS: usize = 16;
+
+/// O que um evento leva além do corpo, no pior caso: versão, tamanhos, nonce,
+/// chave, assinatura e época, a tag da chave da sala e o `Envelope` selado.
+const MAX_FRAMING_BYTES: usize = 512;
+
+/// Tamanho máximo padrão, em bytes, de um evento como chega do gossip: o maior
+/// evento válido, uma imagem com a miniatura cheia, mais o enquadramento. Fica
+/// abaixo do limite do próprio gossip, que só protege o transporte.
+pub const DEFAULT_MAX_EVENT_BYTES: usize = MAX_THUMBNAIL_BYTES + MAX_NAME_BYTES + MAX_FRAMING_BYTES;
+
+const _: () = assert!(DEFAULT_MAX_EVENT_BYTES <= iroh_gossip::proto::DEFAULT_MAX_MESSAGE_SIZE);
+
 // region:       --- structs
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ChatEvent {
     NewMessage {
         actor: NodeId,
+        id: MessageId,
         name: String,
         message: String,
+        reply_to: Option<MessageId>,
     },
     SetName {
         actor: NodeId,
@@ -30,23 +69,200 @@ pub enum ChatEvent {
     NodeLeft {
         actor: NodeId,
     },
+    FileShared {
+        actor: NodeId,
+        name: String,
+        size: u64,
+        hash: Hash,
+    },
+    Image {
+        actor: NodeId,
+        name: String,
+        width: u32,
+        height: u32,
+        thumbnail: Vec<u8>,
+    },
+    Edit {
+        actor: NodeId,
+        target: MessageId,
+        new_text: String,
+    },
+    Delete {
+        actor: NodeId,
+        target: MessageId,
+    },
+    React {
+        actor: NodeId,
+        target: MessageId,
+        emoji: String,
+    },
+    Typing {
+        actor: NodeId,
+    },
+    Receipt {
+        actor: NodeId,
+        kind: ReceiptKind,
+        up_to: MessageId,
+    },
+    AddMember {
+        actor: NodeId,
+        member: NodeId,
+        seq: u64,
+        parents: Vec<MessageId>,
+    },
+    RemoveMember {
+        actor: NodeId,
+        member: NodeId,
+        seq: u64,
+        parents: Vec<MessageId>,
+    },
+    Promote {
+        actor: NodeId,
+        member: NodeId,
+        seq: u64,
+        parents: Vec<MessageId>,
+    },
+    RoomKey {
+        actor: NodeId,
+        seq: u64,
+        parents: Vec<MessageId>,
+        keys: Vec<SealedKey>,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ChatEventBody {
-    NewMessage { name: String, message: String },
-    SetName { name: String },
+    NewMessage {
+        name: String,
+        message: String,
+        reply_to: Option<MessageId>,
+    },
+    SetName {
+        name: String,
+    },
     NodeJoined,
     NodeLeft,
+    FileShared {
+        name: String,
+        size: u64,
+        hash: Hash,
+    },
+    Image {
+        name: String,
+        width: u32,
+        height: u32,
+        thumbnail: Vec<u8>,
+    },
+    Edit {
+        target: MessageId,
+        new_text: String,
+    },
+    Delete {
+        target: MessageId,
+    },
+    React {
+        target: MessageId,
+        emoji: String,
+    },
+    Typing,
+    Receipt {
+        kind: ReceiptKind,
+        up_to: MessageId,
+    },
+    /// Operações de sala. `parents` são as pontas do log que quem assinou
+    /// já tinha visto, e `seq` é um a mais que o maior `seq` delas (ver
+    /// `Room`).
+    AddMember {
+        member: NodeId,
+        seq: u64,
+        parents: Vec<MessageId>,
+    },
+    RemoveMember {
+        member: NodeId,
+        seq: u64,
+        parents: Vec<MessageId>,
+    },
+    Promote {
+        member: NodeId,
+        seq: u64,
+        parents: Vec<MessageId>,
+    },
+    /// Nova chave da sala, selada para cada membro. A época é o `seq`.
+    RoomKey {
+        seq: u64,
+        parents: Vec<MessageId>,
+        keys: Vec<SealedKey>,
+    },
+}
+
+/// Confirmação de que o par recebeu ou leu tudo até uma mensagem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReceiptKind {
+    Delivered,
+    Read,
+}
+
+/// A chave da sala cifrada para um membro, com a chave X25519 derivada do id
+/// dele.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedKey {
+    pub member: NodeId,
+    pub nonce: [u8; 24],
+    pub sealed: Vec<u8>,
 }
 
+/// Onde os eventos valem: o tópico, que entra na assinatura, e o que a sala
+/// exige de quem assina.
+#[derive(Debug, Clone, Copy)]
+pub struct RoomContext {
+    pub topic: TopicId,
+    /// Bits do carimbo de trabalho (ver `stamp_bits`).
+    pub work: u8,
+    /// Aceita eventos do formato sem versão, assinados sem contexto.
+    pub accept_legacy: bool,
+}
+
+/// Id estável de uma mensagem: o hash do evento assinado.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct MessageId([u8; 32]);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SignedChatEvent {
-    // version: u32,
+    /// `VERSION`; 0 marca um evento lido do formato antigo.
+    version: u32,
     body_bytes: Vec<u8>,
     nonce: Nonce,
     key: VerifyingKey,
     sig: Signature,
+    /// Época da chave da sala que cifrou `body_bytes`; `None` se está aberto.
+    epoch: Option<u64>,
+}
+
+/// O formato de antes da versão: sem tópico nem tipo na assinatura, e sempre
+/// aberto.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LegacySignedChatEvent {
+    body_bytes: Vec<u8>,
+    nonce: Nonce,
+    key: VerifyingKey,
+    sig: Signature,
+}
+
+/// Os corpos que o formato antigo conhecia, como eram serializados.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum LegacyChatEventBody {
+    NewMessage { name: String, message: String },
+    SetName { name: String },
+    NodeJoined,
+    NodeLeft,
+}
+
+/// Um evento assinado inteiro (corpo, assinatura e chave do autor) cifrado
+/// com a chave do segredo da sala. Quem repassa sem o segredo só vê bytes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    nonce: [u8; 24],
+    sealed: Vec<u8>,
 }
 
 // endregion:    --- structs
@@ -54,21 +270,93 @@ pub struct SignedChatEvent {
 // region:       --- ChatEvent impl
 
 impl ChatEvent {
-    pub fn builder() -> ChatEventBuilder<Initial, Initial> {
-        ChatEventBuilder::new()
+    pub fn builder(room: RoomContext) -> ChatEventBuilder<Initial, Initial> {
+        ChatEventBuilder::new(room)
     }
 
-    #[allow(dead_code)]
     pub fn actor(&self) -> NodeId {
         *match self {
             Self::NewMessage { actor, .. } => actor,
             Self::SetName { actor, .. } => actor,
             Self::NodeLeft { actor, .. } => actor,
             Self::NodeJoined { actor, .. } => actor,
+            Self::FileShared { actor, .. } => actor,
+            Self::Image { actor, .. } => actor,
+            Self::Edit { actor, .. } => actor,
+            Self::Delete { actor, .. } => actor,
+            Self::React { actor, .. } => actor,
+            Self::Typing { actor } => actor,
+            Self::Receipt { actor, .. } => actor,
+            Self::AddMember { actor, .. } => actor,
+            Self::RemoveMember { actor, .. } => actor,
+            Self::Promote { actor, .. } => actor,
+            Self::RoomKey { actor, .. } => actor,
+        }
+    }
+
+    /// A linha do evento com um rótulo de autor pronto no lugar do padrão de
+    /// `author`, para quem sabe mais sobre o par (apelidos, amigos).
+    pub fn with_author(&self, author: &str) -> String {
+        match self {
+            Self::NewMessage { id, message, .. } => {
+                let id = format!("#{}", id.fmt_short());
+
+                format!("{author}: {} {}", markdown::render(message), id.dimmed())
+            }
+            Self::Image { width, height, .. } => format!("{author}: [image {width}x{height}]"),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl MessageId {
+    /// Prefixo curto mostrado na tela e aceito pelos comandos.
+    pub fn fmt_short(&self) -> String {
+        self.to_string()[..6].to_string()
+    }
+}
+
+impl ReceiptKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Delivered => "delivered",
+            Self::Read => "read",
         }
     }
 }
 
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", base58::encode(self.0).into_string())
+    }
+}
+
+/// O id curto colorido e, entre aspas, o nome escolhido pela pessoa.
+///
+/// As aspas separam o que o par controla (o nome) do que ele não controla (o
+/// id), para um nome como `"ABCDE bob"` não se passar por outro par.
+pub fn author(actor: &NodeId, name: &str) -> String {
+    let (r, g, b) = actor_rbg(actor);
+    let name = sanitize::line(name);
+    let name = name.trim();
+
+    if name.is_empty() {
+        short_id(actor)
+    } else {
+        let name = format!("\"{name}\"");
+
+        format!("{} {}", short_id(actor), name.truecolor(r, g, b))
+    }
+}
+
+/// Os cinco primeiros caracteres do id, na cor do par.
+pub fn short_id(actor: &NodeId) -> String {
+    let (r, g, b) = actor_rbg(actor);
+    let short = &base58::encode(actor).into_string()[..5];
+
+    short.truecolor(r, g, b).to_string()
+}
+
 pub fn actor_rbg(actor: &NodeId) -> (u8, u8, u8) {
     let bytes = actor.as_bytes();
     let hue = (u16::from_be_bytes([bytes[0], bytes[1]]) % 360) as f32;
@@ -80,28 +368,79 @@ pub fn actor_rbg(actor: &NodeId) -> (u8, u8, u8) {
 impl fmt::Display for ChatEvent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::NewMessage {
+            Self::NewMessage { actor, name, .. } | Self::Image { actor, name, .. } => {
+                write!(f, "{}", self.with_author(&author(actor, name)))
+            }
+            Self::SetName { actor, name } => {
+                write!(
+                    f,
+                    "{} is now known as {}",
+                    short_id(actor),
+                    author(actor, name)
+                )
+            }
+            Self::NodeLeft { actor, .. } => write!(f, ""),
+            Self::NodeJoined { actor, .. } => write!(f, ""),
+            Self::FileShared {
                 actor,
                 name,
-                message,
+                size,
+                hash,
             } => {
-                let (r, g, b) = actor_rbg(actor);
-                let name = name.trim();
-                let short = &base58::encode(actor).into_string()[..5];
-                if name.is_empty() {
-                    write!(f, "{}: {message}", short.truecolor(r, g, b))
-                } else {
-                    write!(
-                        f,
-                        "{} {}: {message}",
-                        short.truecolor(r, g, b),
-                        name.truecolor(r, g, b),
-                    )
-                }
+                write!(
+                    f,
+                    "{} shared {} ({size} bytes), /get {hash}",
+                    short_id(actor),
+                    sanitize::line(name),
+                )
+            }
+            Self::Edit { actor, target, .. } => {
+                write!(f, "{} edited #{}", short_id(actor), target.fmt_short())
+            }
+            Self::Delete { actor, target } => {
+                write!(f, "{} deleted #{}", short_id(actor), target.fmt_short())
+            }
+            Self::React {
+                actor,
+                target,
+                emoji,
+            } => {
+                write!(
+                    f,
+                    "{} reacted {} to #{}",
+                    short_id(actor),
+                    sanitize::line(emoji),
+                    target.fmt_short()
+                )
+            }
+            Self::Typing { actor } => {
+                write!(f, "{} is typing…", short_id(actor))
+            }
+            Self::Receipt { actor, kind, up_to } => {
+                write!(
+                    f,
+                    "{} {} up to #{}",
+                    short_id(actor),
+                    kind.as_str(),
+                    up_to.fmt_short()
+                )
+            }
+            Self::AddMember { actor, member, .. }
+            | Self::RemoveMember { actor, member, .. }
+            | Self::Promote { actor, member, .. } => {
+                let verb = match self {
+                    Self::AddMember { .. } => "added",
+                    Self::RemoveMember { .. } => "removed",
+                    _ => "promoted",
+                };
+
+                write!(f, "{} {verb} {}", short_id(actor), short_id(member))
+            }
+            Self::RoomKey { actor, keys, .. } => {
+                let line = format!("rotated the room key for {} members", keys.len());
+
+                write!(f, "{} {}", short_id(actor), line.dimmed())
             }
-            Self::SetName { actor, name } => write!(f, ""),
-            Self::NodeLeft { actor, .. } => write!(f, ""),
-            Self::NodeJoined { actor, .. } => write!(f, ""),
         }
     }
 }
@@ -111,38 +450,372 @@ impl fmt::Display for ChatEvent {
 // region:       --- SignedChatEvent impl
 
 impl SignedChatEvent {
-    pub fn verify_into(self) -> Result<ChatEvent, SignatureError> {
-        let body_bytes_len = self.body_bytes.len();
-        let mut with_nonce = self.body_bytes;
+    /// Lê um evento do fio, no formato atual ou no antigo, sem versão.
+    ///
+    /// O `version` vem primeiro justamente para o próximo formato poder ser
+    /// reconhecido aqui. O antigo começa pelo tamanho do corpo, que pode se
+    /// passar por qualquer versão, então só vale se os bytes forem exatamente
+    /// um evento antigo; `verify_into` decide se ele ainda é aceito. Qualquer
+    /// outra versão é recusada.
+    pub fn decode(raw: &[u8]) -> Result<Self, SignatureError> {
+        let (version, _) = postcard::take_from_bytes::<u32>(raw)?;
+
+        if version == VERSION
+            && let Ok((event, [])) = postcard::take_from_bytes::<Self>(raw)
+        {
+            return Ok(event);
+        }
 
-        with_nonce.extend_from_slice(&self.nonce);
+        if let Some(event) = LegacySignedChatEvent::read(raw) {
+            return Ok(event.into());
+        }
+
+        match version {
+            VERSION => Err(PostcardError::DeserializeBadEncoding.into()),
+            version => Err(SignatureError::Version(version)),
+        }
+    }
 
-        self.key.verify_strict(&with_nonce, &self.sig)?;
+    /// Hash do evento inteiro, assinatura e nonce inclusos, no formato em que
+    /// foi publicado.
+    pub fn id(&self) -> MessageId {
+        let bytes = if self.version == 0 {
+            postcard::to_allocvec(&LegacySignedChatEvent::from(self.clone())).unwrap()
+        } else {
+            self.to_vec()
+        };
+
+        MessageId(*blake3::hash(&bytes).as_bytes())
+    }
+
+    /// Confere o carimbo de trabalho, a assinatura (com o contexto da sala) e
+    /// os limites.
+    pub fn verify_into(self, room: &RoomContext) -> Result<ChatEvent, SignatureError> {
+        // o carimbo custa um hash; vem antes da assinatura, que custa mais
+        let prefix = stamp_prefix(&self.key, room, &self.body_bytes);
+
+        if stamp_bits(&prefix, &self.nonce) < u32::from(room.work) {
+            return Err(SignatureError::Stamp);
+        }
+
+        let id = self.id();
+        let event_body = match self.version {
+            0 => postcard::from_bytes::<LegacyChatEventBody>(&self.body_bytes)?.into(),
+            _ => postcard::from_bytes::<ChatEventBody>(&self.body_bytes)?,
+        };
+        let message = match self.version {
+            VERSION => signing_message(room, &event_body, &self.body_bytes, &self.nonce),
+            0 if room.accept_legacy => [self.body_bytes.as_slice(), &self.nonce].concat(),
+            version => return Err(SignatureError::Version(version)),
+        };
+
+        self.key.verify_strict(&message, &self.sig)?;
+
+        event_body.check_limits()?;
 
-        let body_bytes = &with_nonce[..body_bytes_len];
-        let event_body = postcard::from_bytes(body_bytes)?;
         let actor = NodeId::from(self.key);
 
-        let event = match event_body {
-            ChatEventBody::NewMessage { name, message } => ChatEvent::NewMessage {
+        Ok(event_body.into_event(actor, id))
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        postcard::to_allocvec(self).unwrap()
+    }
+
+    pub fn epoch(&self) -> Option<u64> {
+        self.epoch
+    }
+
+    /// Operações de sala andam sempre abertas: é por elas que quem chega
+    /// descobre os membros e a própria chave.
+    pub fn is_room_op(&self) -> bool {
+        matches!(
+            postcard::from_bytes::<ChatEventBody>(&self.body_bytes),
+            Ok(ChatEventBody::AddMember { .. }
+                | ChatEventBody::RemoveMember { .. }
+                | ChatEventBody::Promote { .. }
+                | ChatEventBody::RoomKey { .. })
+        )
+    }
+
+    /// Cifra o corpo com a chave da sala na época `epoch`.
+    ///
+    /// A assinatura continua sendo do corpo aberto, então só quem tem a chave
+    /// consegue verificar o evento. O id também não muda.
+    pub fn encrypt(mut self, epoch: u64, key: &[u8; 32]) -> Self {
+        let payload = Payload {
+            msg: &self.body_bytes,
+            aad: &self.aad(epoch),
+        };
+        let body_bytes = XChaCha20Poly1305::new(key.into())
+            .encrypt(&self.xnonce(), payload)
+            .unwrap();
+
+        self.body_bytes = body_bytes;
+        self.epoch = Some(epoch);
+        self
+    }
+
+    /// Desfaz o `encrypt`, devolvendo o evento como foi assinado.
+    pub fn decrypt(mut self, key: &[u8; 32]) -> Result<Self, SignatureError> {
+        let Some(epoch) = self.epoch else {
+            return Ok(self);
+        };
+        let payload = Payload {
+            msg: &self.body_bytes,
+            aad: &self.aad(epoch),
+        };
+        let body_bytes = XChaCha20Poly1305::new(key.into())
+            .decrypt(&self.xnonce(), payload)
+            .map_err(|_| SignatureError::Decrypt)?;
+
+        self.body_bytes = body_bytes;
+        self.epoch = None;
+
+        Ok(self)
+    }
+
+    /// O nonce do evento já é aleatório; completa com zeros até 24 bytes.
+    fn xnonce(&self) -> XNonce {
+        let mut nonce = [0u8; 24];
+
+        nonce[..16].copy_from_slice(&self.nonce);
+
+        nonce.into()
+    }
+
+    /// Amarra a época e o autor ao texto cifrado.
+    fn aad(&self, epoch: u64) -> Vec<u8> {
+        let mut aad = epoch.to_be_bytes().to_vec();
+
+        aad.extend_from_slice(self.key.as_bytes());
+
+        aad
+    }
+}
+
+// endregion:    --- SignedChatEvent impl
+
+// region:       --- Envelope impl
+
+impl Envelope {
+    /// Fecha um evento já serializado.
+    pub fn seal(raw: &[u8], key: &[u8; 32]) -> Self {
+        let nonce = rand::random::<[u8; 24]>();
+        let sealed = XChaCha20Poly1305::new(key.into())
+            .encrypt(&nonce.into(), raw)
+            .unwrap();
+
+        Self { nonce, sealed }
+    }
+
+    /// Devolve o evento serializado que estava dentro.
+    pub fn open(&self, key: &[u8; 32]) -> Result<Vec<u8>, SignatureError> {
+        XChaCha20Poly1305::new(key.into())
+            .decrypt(&self.nonce.into(), self.sealed.as_slice())
+            .map_err(|_| SignatureError::Decrypt)
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        postcard::to_allocvec(self).unwrap()
+    }
+}
+
+// endregion:    --- Envelope impl
+
+// region:       --- ChatEventBody impl
+
+impl From<LegacySignedChatEvent> for SignedChatEvent {
+    fn from(event: LegacySignedChatEvent) -> Self {
+        Self {
+            version: 0,
+            body_bytes: event.body_bytes,
+            nonce: event.nonce,
+            key: event.key,
+            sig: event.sig,
+            epoch: None,
+        }
+    }
+}
+
+impl From<SignedChatEvent> for LegacySignedChatEvent {
+    fn from(event: SignedChatEvent) -> Self {
+        Self {
+            body_bytes: event.body_bytes,
+            nonce: event.nonce,
+            key: event.key,
+            sig: event.sig,
+        }
+    }
+}
+
+impl LegacySignedChatEvent {
+    /// Só aceita os bytes se eles forem inteiros um evento antigo, com um
+    /// corpo que o formato antigo conhecia.
+    fn read(raw: &[u8]) -> Option<Self> {
+        let Ok((event, [])) = postcard::take_from_bytes::<Self>(raw) else {
+            return None;
+        };
+        let Ok((_, [])) = postcard::take_from_bytes::<LegacyChatEventBody>(&event.body_bytes)
+        else {
+            return None;
+        };
+
+        Some(event)
+    }
+}
+
+impl From<LegacyChatEventBody> for ChatEventBody {
+    fn from(body: LegacyChatEventBody) -> Self {
+        match body {
+            LegacyChatEventBody::NewMessage { name, message } => Self::NewMessage {
+                name,
+                message,
+                reply_to: None,
+            },
+            LegacyChatEventBody::SetName { name } => Self::SetName { name },
+            LegacyChatEventBody::NodeJoined => Self::NodeJoined,
+            LegacyChatEventBody::NodeLeft => Self::NodeLeft,
+        }
+    }
+}
+
+impl ChatEventBody {
+    /// O evento como fica depois de verificado, assinado por `actor`.
+    fn into_event(self, actor: NodeId, id: MessageId) -> ChatEvent {
+        match self {
+            Self::NewMessage {
+                name,
+                message,
+                reply_to,
+            } => ChatEvent::NewMessage {
                 actor,
+                id,
                 name,
                 message,
+                reply_to,
             },
-            ChatEventBody::SetName { name } => ChatEvent::SetName { actor, name },
-            ChatEventBody::NodeJoined => ChatEvent::NodeJoined { actor },
-            ChatEventBody::NodeLeft => ChatEvent::NodeLeft { actor },
-        };
+            Self::SetName { name } => ChatEvent::SetName { actor, name },
+            Self::NodeJoined => ChatEvent::NodeJoined { actor },
+            Self::NodeLeft => ChatEvent::NodeLeft { actor },
+            Self::FileShared { name, size, hash } => ChatEvent::FileShared {
+                actor,
+                name,
+                size,
+                hash,
+            },
+            Self::Image {
+                name,
+                width,
+                height,
+                thumbnail,
+            } => ChatEvent::Image {
+                actor,
+                name,
+                width,
+                height,
+                thumbnail,
+            },
+            Self::Edit { target, new_text } => ChatEvent::Edit {
+                actor,
+                target,
+                new_text,
+            },
+            Self::Delete { target } => ChatEvent::Delete { actor, target },
+            Self::React { target, emoji } => ChatEvent::React {
+                actor,
+                target,
+                emoji,
+            },
+            Self::Typing => ChatEvent::Typing { actor },
+            Self::Receipt { kind, up_to } => ChatEvent::Receipt { actor, kind, up_to },
+            Self::AddMember {
+                member,
+                seq,
+                parents,
+            } => ChatEvent::AddMember {
+                actor,
+                member,
+                seq,
+                parents,
+            },
+            Self::RemoveMember {
+                member,
+                seq,
+                parents,
+            } => ChatEvent::RemoveMember {
+                actor,
+                member,
+                seq,
+                parents,
+            },
+            Self::Promote {
+                member,
+                seq,
+                parents,
+            } => ChatEvent::Promote {
+                actor,
+                member,
+                seq,
+                parents,
+            },
+            Self::RoomKey { seq, parents, keys } => ChatEvent::RoomKey {
+                actor,
+                seq,
+                parents,
+                keys,
+            },
+        }
+    }
 
-        Ok(event)
+    /// Nome do tipo do evento, que entra na assinatura.
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::NewMessage { .. } => "new_message",
+            Self::SetName { .. } => "set_name",
+            Self::NodeJoined => "node_joined",
+            Self::NodeLeft => "node_left",
+            Self::FileShared { .. } => "file_shared",
+            Self::Image { .. } => "image",
+            Self::Edit { .. } => "edit",
+            Self::Delete { .. } => "delete",
+            Self::React { .. } => "react",
+            Self::Typing => "typing",
+            Self::Receipt { .. } => "receipt",
+            Self::AddMember { .. } => "add_member",
+            Self::RemoveMember { .. } => "remove_member",
+            Self::Promote { .. } => "promote",
+            Self::RoomKey { .. } => "room_key",
+        }
     }
+    /// Recusa campos maiores do que qualquer cliente honesto mandaria.
+    fn check_limits(&self) -> Result<(), SignatureError> {
+        let too_long = match self {
+            Self::NewMessage { name, message, .. } => {
+                name.len() > MAX_NAME_BYTES || message.len() > MAX_TEXT_BYTES
+            }
+            Self::SetName { name } => name.len() > MAX_NAME_BYTES,
+            Self::Image {
+                name, thumbnail, ..
+            } => name.len() > MAX_NAME_BYTES || thumbnail.len() > MAX_THUMBNAIL_BYTES,
+            Self::FileShared { name, .. } => name.len() > MAX_FILE_NAME_BYTES,
+            Self::Edit { new_text, .. } => new_text.len() > MAX_TEXT_BYTES,
+            Self::AddMember { parents, .. }
+            | Self::RemoveMember { parents, .. }
+            | Self::Promote { parents, .. }
+            | Self::RoomKey { parents, .. } => parents.len() > MAX_PARENTS,
+            _ => false,
+        };
 
-    pub fn to_vec(&self) -> Vec<u8> {
-        postcard::to_allocvec(self).unwrap()
+        if too_long {
+            return Err(SignatureError::TooLong);
+        }
+
+        Ok(())
     }
 }
 
-// endregion:    --- SignedChatEvent impl
+// endregion:    --- ChatEventBody impl
 
 // Initial state
 pub struct Initial;
@@ -151,115 +824,387 @@ pub struct Initial;
 
 pub trait EventState {}
 
+/// Um estado de evento que vira corpo para ser assinado.
+///
+/// Todos os `sign` passam por aqui. O próprio `ChatEventBody` implementa,
+/// então qualquer corpo, inclusive de variantes sem método no builder, pode
+/// ser assinado com `body`.
+pub trait IntoBody: EventState {
+    fn into_body(self) -> ChatEventBody;
+}
+
 pub struct NewMessage {
     name: String,
     message: String,
+    reply_to: Option<MessageId>,
+}
+
+pub struct SetName {
+    name: String,
 }
 
 pub struct NodeJoined;
 
 pub struct NodeLeft;
 
+pub struct FileShared {
+    name: String,
+    size: u64,
+    hash: Hash,
+}
+
+pub struct Image {
+    name: String,
+    width: u32,
+    height: u32,
+    thumbnail: Vec<u8>,
+}
+
+pub struct Edit {
+    target: MessageId,
+    new_text: String,
+}
+
+pub struct Delete {
+    target: MessageId,
+}
+
+pub struct React {
+    target: MessageId,
+    emoji: String,
+}
+
+pub struct Typing;
+
+pub struct Receipt {
+    kind: ReceiptKind,
+    up_to: MessageId,
+}
+
+pub struct RoomKey {
+    seq: u64,
+    parents: Vec<MessageId>,
+    keys: Vec<SealedKey>,
+}
+
 impl EventState for Initial {}
 
 impl EventState for NewMessage {}
 
+impl EventState for SetName {}
+
 impl EventState for NodeJoined {}
 
 impl EventState for NodeLeft {}
 
+impl EventState for FileShared {}
+
+impl EventState for Image {}
+
+impl EventState for Edit {}
+
+impl EventState for Delete {}
+
+impl EventState for React {}
+
+impl EventState for Typing {}
+
+impl EventState for Receipt {}
+
+impl EventState for RoomKey {}
+
+impl EventState for ChatEventBody {}
+
+impl IntoBody for NewMessage {
+    fn into_body(self) -> ChatEventBody {
+        ChatEventBody::NewMessage {
+            name: self.name,
+            message: self.message,
+            reply_to: self.reply_to,
+        }
+    }
+}
+
+impl IntoBody for SetName {
+    fn into_body(self) -> ChatEventBody {
+        ChatEventBody::SetName { name: self.name }
+    }
+}
+
+impl IntoBody for NodeJoined {
+    fn into_body(self) -> ChatEventBody {
+        ChatEventBody::NodeJoined
+    }
+}
+
+impl IntoBody for NodeLeft {
+    fn into_body(self) -> ChatEventBody {
+        ChatEventBody::NodeLeft
+    }
+}
+
+impl IntoBody for FileShared {
+    fn into_body(self) -> ChatEventBody {
+        ChatEventBody::FileShared {
+            name: self.name,
+            size: self.size,
+            hash: self.hash,
+        }
+    }
+}
+
+impl IntoBody for Image {
+    fn into_body(self) -> ChatEventBody {
+        ChatEventBody::Image {
+            name: self.name,
+            width: self.width,
+            height: self.height,
+            thumbnail: self.thumbnail,
+        }
+    }
+}
+
+impl IntoBody for Edit {
+    fn into_body(self) -> ChatEventBody {
+        ChatEventBody::Edit {
+            target: self.target,
+            new_text: self.new_text,
+        }
+    }
+}
+
+impl IntoBody for Delete {
+    fn into_body(self) -> ChatEventBody {
+        ChatEventBody::Delete {
+            target: self.target,
+        }
+    }
+}
+
+impl IntoBody for React {
+    fn into_body(self) -> ChatEventBody {
+        ChatEventBody::React {
+            target: self.target,
+            emoji: self.emoji,
+        }
+    }
+}
+
+impl IntoBody for Typing {
+    fn into_body(self) -> ChatEventBody {
+        ChatEventBody::Typing
+    }
+}
+
+impl IntoBody for Receipt {
+    fn into_body(self) -> ChatEventBody {
+        ChatEventBody::Receipt {
+            kind: self.kind,
+            up_to: self.up_to,
+        }
+    }
+}
+
+impl IntoBody for RoomKey {
+    fn into_body(self) -> ChatEventBody {
+        ChatEventBody::RoomKey {
+            seq: self.seq,
+            parents: self.parents,
+            keys: self.keys,
+        }
+    }
+}
+
+impl IntoBody for ChatEventBody {
+    fn into_body(self) -> ChatEventBody {
+        self
+    }
+}
+
 // endregion:    --- EventState
 
 // region:       --- SignState
 
 pub trait SignState {}
 
-pub struct ReadyToSign;
-
-#[allow(dead_code)]
-pub struct Signed {
-    key: VerifyingKey,
+/// Quem assina já foi escolhido, com `signer`; o `sign` do fim não leva chave.
+pub struct Signer {
+    key: SigningKey,
 }
 
 impl SignState for Initial {}
 
-impl SignState for ReadyToSign {}
-
-impl SignState for Signed {}
+impl SignState for Signer {}
 
 // endregion:    --- SignState
 
-// TODO: fazer poder usar o sign antes também
-
-#[allow(dead_code)]
 pub struct ChatEventBuilder<E: EventState, S: SignState> {
     sign: S,
     event: E,
+    room: RoomContext,
 }
 
 // region:       --- impl ChatEventBuilder
 
 impl ChatEventBuilder<Initial, Initial> {
-    pub fn new() -> Self {
+    /// Os eventos saem assinados para a sala `room`, com o carimbo que ela
+    /// exige.
+    pub fn new(room: RoomContext) -> Self {
         Self {
             sign: Initial,
             event: Initial,
+            room,
+        }
+    }
+}
+
+impl<E: EventState> ChatEventBuilder<E, Initial> {
+    /// Escolhe quem assina, antes ou depois do evento.
+    pub fn signer(self, key: &SigningKey) -> ChatEventBuilder<E, Signer> {
+        ChatEventBuilder {
+            sign: Signer { key: key.clone() },
+            event: self.event,
+            room: self.room,
         }
     }
+}
 
+impl<S: SignState> ChatEventBuilder<Initial, S> {
     pub fn new_message(
         self,
         name: impl Into<String>,
         message: impl Into<String>,
-    ) -> ChatEventBuilder<NewMessage, ReadyToSign> {
-        ChatEventBuilder {
-            sign: ReadyToSign,
-            event: NewMessage {
-                name: name.into(),
-                message: message.into(),
-            },
-        }
+    ) -> ChatEventBuilder<NewMessage, S> {
+        self.event(NewMessage {
+            name: name.into(),
+            message: message.into(),
+            reply_to: None,
+        })
     }
 
-    pub fn node_joined(self) -> ChatEventBuilder<NodeJoined, ReadyToSign> {
-        ChatEventBuilder {
-            sign: ReadyToSign,
-            event: NodeJoined,
-        }
+    pub fn set_name(self, name: impl Into<String>) -> ChatEventBuilder<SetName, S> {
+        self.event(SetName { name: name.into() })
+    }
+
+    pub fn node_joined(self) -> ChatEventBuilder<NodeJoined, S> {
+        self.event(NodeJoined)
     }
 
-    pub fn node_left(self) -> ChatEventBuilder<NodeLeft, ReadyToSign> {
+    pub fn node_left(self) -> ChatEventBuilder<NodeLeft, S> {
+        self.event(NodeLeft)
+    }
+
+    pub fn file_shared(
+        self,
+        name: impl Into<String>,
+        size: u64,
+        hash: Hash,
+    ) -> ChatEventBuilder<FileShared, S> {
+        self.event(FileShared {
+            name: name.into(),
+            size,
+            hash,
+        })
+    }
+
+    pub fn image(
+        self,
+        name: impl Into<String>,
+        width: u32,
+        height: u32,
+        thumbnail: Vec<u8>,
+    ) -> ChatEventBuilder<Image, S> {
+        self.event(Image {
+            name: name.into(),
+            width,
+            height,
+            thumbnail,
+        })
+    }
+
+    pub fn edit(self, target: MessageId, new_text: impl Into<String>) -> ChatEventBuilder<Edit, S> {
+        self.event(Edit {
+            target,
+            new_text: new_text.into(),
+        })
+    }
+
+    pub fn delete(self, target: MessageId) -> ChatEventBuilder<Delete, S> {
+        self.event(Delete { target })
+    }
+
+    pub fn react(self, target: MessageId, emoji: impl Into<String>) -> ChatEventBuilder<React, S> {
+        self.event(React {
+            target,
+            emoji: emoji.into(),
+        })
+    }
+
+    pub fn typing(self) -> ChatEventBuilder<Typing, S> {
+        self.event(Typing)
+    }
+
+    pub fn receipt(self, kind: ReceiptKind, up_to: MessageId) -> ChatEventBuilder<Receipt, S> {
+        self.event(Receipt { kind, up_to })
+    }
+
+    pub fn room_key(
+        self,
+        seq: u64,
+        parents: Vec<MessageId>,
+        keys: Vec<SealedKey>,
+    ) -> ChatEventBuilder<RoomKey, S> {
+        self.event(RoomKey { seq, parents, keys })
+    }
+
+    /// Um corpo pronto, de qualquer variante.
+    pub fn body(self, body: ChatEventBody) -> ChatEventBuilder<ChatEventBody, S> {
+        self.event(body)
+    }
+
+    fn event<E: EventState>(self, event: E) -> ChatEventBuilder<E, S> {
         ChatEventBuilder {
-            sign: ReadyToSign,
-            event: NodeLeft,
+            sign: self.sign,
+            event,
+            room: self.room,
         }
     }
 }
 
-impl ChatEventBuilder<NewMessage, ReadyToSign> {
-    pub fn sign(self, key: &SigningKey) -> SignedChatEvent {
-        let body = ChatEventBody::NewMessage {
-            name: self.event.name,
-            message: self.event.message,
-        };
-
-        sign_chat_event(body, key)
+impl<S: SignState> ChatEventBuilder<NewMessage, S> {
+    /// Marca a mensagem como resposta a outra.
+    pub fn reply_to(mut self, target: MessageId) -> Self {
+        self.event.reply_to = Some(target);
+        self
     }
 }
 
-impl ChatEventBuilder<NodeJoined, ReadyToSign> {
-    pub fn sign(self, key: &SigningKey) -> SignedChatEvent {
-        let body = ChatEventBody::NodeJoined;
+impl<E: IntoBody> ChatEventBuilder<E, Initial> {
+    pub async fn sign(self, key: &SigningKey) -> SignedChatEvent {
+        self.signer(key).sign().await
+    }
 
-        sign_chat_event(body, key)
+    /// Assina e devolve também o evento como os outros vão recebê-lo, para o
+    /// eco local.
+    pub async fn sign_with_echo(self, key: &SigningKey) -> (SignedChatEvent, ChatEvent) {
+        self.signer(key).sign_with_echo().await
     }
 }
 
-impl ChatEventBuilder<NodeLeft, ReadyToSign> {
-    pub fn sign(self, key: &SigningKey) -> SignedChatEvent {
-        let body = ChatEventBody::NodeLeft;
+impl<E: IntoBody> ChatEventBuilder<E, Signer> {
+    pub async fn sign(self) -> SignedChatEvent {
+        sign_chat_event(self.event.into_body(), &self.sign.key, &self.room).await
+    }
+
+    /// Assina e devolve também o evento como os outros vão recebê-lo, para o
+    /// eco local.
+    pub async fn sign_with_echo(self) -> (SignedChatEvent, ChatEvent) {
+        let body = self.event.into_body();
+        let signed = sign_chat_event(body.clone(), &self.sign.key, &self.room).await;
+        let actor = NodeId::from(self.sign.key.verifying_key());
+        let local = body.into_event(actor, signed.id());
 
-        sign_chat_event(body, key)
+        (signed, local)
     }
 }
 
@@ -272,25 +1217,259 @@ impl ChatEventBuilder<NodeLeft, ReadyToSign> {
 pub enum SignatureError {
     Dalek(#[from] DalekError),
     Postcard(#[from] PostcardError),
+    TooLong,
+    Decrypt,
+    Stamp,
+    Version(u32),
 }
 
-fn sign_chat_event(event: ChatEventBody, key: &SigningKey) -> SignedChatEvent {
-    let mut bytes = postcard::to_allocvec(&event).unwrap();
-    let body_bytes_len = bytes.len();
-    let nonce = rand::random::<Nonce>();
-
-    bytes.extend_from_slice(&nonce);
-
-    let sig = key.sign(&bytes);
-
-    bytes.truncate(body_bytes_len);
+async fn sign_chat_event(
+    event: ChatEventBody,
+    key: &SigningKey,
+    room: &RoomContext,
+) -> SignedChatEvent {
+    let bytes = postcard::to_allocvec(&event).unwrap();
+    let nonce = mine(stamp_prefix(&key.verifying_key(), room, &bytes), room.work).await;
+    let sig = key.sign(&signing_message(room, &event, &bytes, &nonce));
 
     SignedChatEvent {
+        version: VERSION,
         body_bytes: bytes,
         nonce,
         key: key.verifying_key(),
         sig,
+        epoch: None,
     }
 }
 
+/// O que a assinatura cobre: `SIGNING_TAG`, o tópico, o tipo do evento, o
+/// corpo e o nonce. Uma assinatura de uma sala não vale em outra, nem para
+/// outro tipo de evento.
+fn signing_message(
+    room: &RoomContext,
+    event: &ChatEventBody,
+    body_bytes: &[u8],
+    nonce: &Nonce,
+) -> Vec<u8> {
+    [
+        SIGNING_TAG,
+        &[0],
+        room.topic.as_bytes(),
+        event.kind().as_bytes(),
+        &[0],
+        body_bytes,
+        nonce,
+    ]
+    .concat()
+}
+
+/// Procura um nonce cujo carimbo tenha pelo menos `work` zeros.
+///
+/// Começa de um nonce aleatório e conta a partir dele, então dois eventos
+/// iguais continuam com nonces diferentes. Com `work` alto a busca leva
+/// segundos, então roda numa thread de bloqueio, fora do runtime.
+async fn mine(prefix: blake3::Hash, work: u8) -> Nonce {
+    let mut nonce = rand::random::<Nonce>();
+
+    if work == 0 {
+        return nonce;
+    }
+
+    tokio::task::spawn_blocking(move || {
+        while stamp_bits(&prefix, &nonce) < u32::from(work) {
+            let counter = u64::from_be_bytes(nonce[8..].try_into().unwrap());
+
+            nonce[8..].copy_from_slice(&counter.wrapping_add(1).to_be_bytes());
+        }
+
+        nonce
+    })
+    .await
+    .unwrap()
+}
+
+/// O que o carimbo prende: a chave de quem assina, o tópico e o corpo. Um
+/// carimbo minerado para uma chave ou sala não serve em outra.
+fn stamp_prefix(key: &VerifyingKey, room: &RoomContext, body_bytes: &[u8]) -> blake3::Hash {
+    blake3::Hasher::new()
+        .update(key.as_bytes())
+        .update(room.topic.as_bytes())
+        .update(body_bytes)
+        .finalize()
+}
+
+/// Quantos zeros abrem `blake3(blake3(chave || tópico || corpo) || nonce)`.
+fn stamp_bits(prefix: &blake3::Hash, nonce: &Nonce) -> u32 {
+    let mut hasher = blake3::Hasher::new();
+
+    hasher.update(prefix.as_bytes());
+    hasher.update(nonce);
+
+    let stamp = hasher.finalize();
+
+    u128::from_be_bytes(stamp.as_bytes()[..16].try_into().unwrap()).leading_zeros()
+}
+
 // endregion:    --- utils
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(accept_legacy: bool) -> RoomContext {
+        RoomContext {
+            topic: TopicId::from_bytes([7; 32]),
+            work: 0,
+            accept_legacy,
+        }
+    }
+
+    /// Um evento como o formato antigo assinava: só corpo e nonce.
+    fn legacy(body: &LegacyChatEventBody, key: &SigningKey) -> Vec<u8> {
+        let body_bytes = postcard::to_allocvec(body).unwrap();
+        let nonce = rand::random::<Nonce>();
+        let sig = key.sign(&[body_bytes.as_slice(), &nonce].concat());
+        let event = LegacySignedChatEvent {
+            body_bytes,
+            nonce,
+            key: key.verifying_key(),
+            sig,
+        };
+
+        postcard::to_allocvec(&event).unwrap()
+    }
+
+    #[test]
+    fn legacy_events_need_accept_legacy() {
+        let key = SigningKey::from_bytes(&rand::random());
+        let message = LegacyChatEventBody::NewMessage {
+            name: "ana".to_string(),
+            message: "oi".to_string(),
+        };
+
+        // o corpo de `NodeJoined` tem um byte, e o tamanho dele lê como `VERSION`
+        for body in [message, LegacyChatEventBody::NodeJoined] {
+            let raw = legacy(&body, &key);
+            let event = SignedChatEvent::decode(&raw).unwrap();
+
+            assert_eq!(event.id(), MessageId(*blake3::hash(&raw).as_bytes()));
+            assert!(matches!(
+                event.clone().verify_into(&context(false)),
+                Err(SignatureError::Version(0))
+            ));
+            assert!(event.verify_into(&context(true)).is_ok());
+        }
+    }
+
+    /// Passa o evento pelo mesmo caminho do envio, com a chave da sala e o
+    /// modo selado, e de volta pelo do recebimento.
+    fn round_trip(signed: SignedChatEvent) -> (usize, Result<ChatEvent, SignatureError>) {
+        let room_key = rand::random::<[u8; 32]>();
+        let sealed_key = rand::random::<[u8; 32]>();
+        let raw = signed.encrypt(u64::MAX, &room_key).to_vec();
+        let wire = Envelope::seal(&raw, &sealed_key).to_vec();
+        let raw = postcard::from_bytes::<Envelope>(&wire)
+            .unwrap()
+            .open(&sealed_key)
+            .unwrap();
+        let event = SignedChatEvent::decode(&raw)
+            .unwrap()
+            .decrypt(&room_key)
+            .unwrap()
+            .verify_into(&context(false));
+
+        (wire.len(), event)
+    }
+
+    #[test]
+    fn largest_events_fit_the_default_limit() {
+        let key = SigningKey::from_bytes(&rand::random());
+        let name = "n".repeat(MAX_NAME_BYTES);
+        let builder = || ChatEvent::builder(context(false));
+        let image = builder().image(&name, u32::MAX, u32::MAX, vec![0xff; MAX_THUMBNAIL_BYTES]);
+        let message = builder()
+            .new_message(&name, "m".repeat(MAX_TEXT_BYTES))
+            .reply_to(MessageId([0xff; 32]));
+        let signed = futures_lite::future::block_on(async {
+            [image.sign(&key).await, message.sign(&key).await]
+        });
+
+        for signed in signed {
+            let (size, event) = round_trip(signed);
+
+            assert!(event.is_ok());
+            assert!(size <= DEFAULT_MAX_EVENT_BYTES, "{size}");
+        }
+
+        let too_big = builder().image(&name, 1, 1, vec![0; MAX_THUMBNAIL_BYTES + 1]);
+        let signed = futures_lite::future::block_on(too_big.sign(&key));
+
+        assert!(matches!(round_trip(signed).1, Err(SignatureError::TooLong)));
+    }
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        let key = SigningKey::from_bytes(&rand::random());
+        let mut event = futures_lite::future::block_on(
+            ChatEvent::builder(context(false))
+                .new_message("ana", "oi")
+                .sign(&key),
+        );
+
+        assert!(SignedChatEvent::decode(&event.to_vec()).is_ok());
+
+        event.version = 2;
+
+        assert!(matches!(
+            SignedChatEvent::decode(&event.to_vec()),
+            Err(SignatureError::Version(2))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stamps_do_not_move_to_another_key_or_topic() {
+        let (alice, bob) = (
+            SigningKey::from_bytes(&rand::random()),
+            SigningKey::from_bytes(&rand::random()),
+        );
+        // com 16 bits, um nonce alheio passar por acaso é 1 em 65536
+        let room = RoomContext {
+            work: 16,
+            ..context(false)
+        };
+        let other_room = RoomContext {
+            topic: TopicId::from_bytes([8; 32]),
+            ..room
+        };
+        let body = ChatEventBody::NewMessage {
+            name: "ana".to_string(),
+            message: "oi".to_string(),
+            reply_to: None,
+        };
+        let signed = sign_chat_event(body.clone(), &alice, &room).await;
+        // o mesmo corpo e o mesmo nonce, assinados de novo como seriam
+        let resign = |key: &SigningKey, room: &RoomContext| {
+            let body_bytes = postcard::to_allocvec(&body).unwrap();
+            let sig = key.sign(&signing_message(room, &body, &body_bytes, &signed.nonce));
+
+            SignedChatEvent {
+                version: VERSION,
+                body_bytes,
+                nonce: signed.nonce,
+                key: key.verifying_key(),
+                sig,
+                epoch: None,
+            }
+        };
+
+        assert!(resign(&alice, &room).verify_into(&room).is_ok());
+        assert!(matches!(
+            resign(&bob, &room).verify_into(&room),
+            Err(SignatureError::Stamp)
+        ));
+        assert!(matches!(
+            resign(&alice, &other_room).verify_into(&other_room),
+            Err(SignatureError::Stamp)
+        ));
+    }
+}
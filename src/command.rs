@@ -0,0 +1,179 @@
+use std::path::PathBuf;
+
+use iroh_blobs::Hash;
+use serde_json::Error as JsonError;
+use thiserror::Error as ThisError;
+
+use crate::jsonl::JsonCommand;
+
+// region:       --- Command
+
+/// Uma ação pedida pelo usuário, vinda do terminal ou do stdin em jsonl.
+#[derive(Debug, Clone)]
+pub enum Command {
+    Send(String),
+    Name(String),
+    Join,
+    Leave,
+    SendFile(PathBuf),
+    Get(Hash),
+    Image(PathBuf),
+    /// Prefixo do id da mensagem e o novo texto.
+    Edit(String, String),
+    /// Prefixo do id da mensagem.
+    Delete(String),
+    /// Prefixo do id da mensagem respondida e o texto.
+    Reply(String, String),
+    /// Prefixo do id da mensagem raiz.
+    Thread(String),
+    /// Prefixo do id da mensagem e o emoji.
+    React(String, String),
+    /// Prefixo do id da mensagem.
+    Seen(String),
+    /// O par (id, nome ou apelido) e o apelido local para ele.
+    Nick(String, String),
+    /// O par e, se vier, a decisão: `Some(true)` marca como verificado.
+    Verify(String, Option<bool>),
+    /// O par e se deve ficar bloqueado (`/block`) ou não (`/unblock`).
+    Block(String, bool),
+    /// O par e se deve ficar silenciado (`/mute`) ou não (`/unmute`).
+    Mute(String, bool),
+    /// O par a adicionar à sala com dono.
+    AddMember(String),
+    /// O par a remover da sala com dono.
+    RemoveMember(String),
+    /// O membro a tornar admin.
+    Promote(String),
+    Members,
+    /// O par e o texto da mensagem direta.
+    Direct(String, String),
+    Stats,
+    Exit,
+}
+
+impl Command {
+    /// Interpreta uma linha digitada. Linhas vazias viram `None`.
+    pub fn parse(line: &str) -> Result<Option<Self>, CommandError> {
+        let line = line.trim();
+
+        if !line.starts_with('/') {
+            if line.is_empty() {
+                return Ok(None);
+            }
+
+            return Ok(Some(Self::Send(line.to_string())));
+        }
+
+        let (action, rest) = match line.split_once(char::is_whitespace) {
+            Some((cmd, rest)) => (cmd, rest.trim()),
+            None => (line, ""),
+        };
+
+        let command = match action {
+            "/send" => {
+                if rest.is_empty() {
+                    return Ok(None);
+                }
+
+                Self::Send(rest.to_string())
+            }
+            "/name" => Self::Name(rest.to_string()),
+            "/join" => Self::Join,
+            "/leave" => Self::Leave,
+            "/send-file" if !rest.is_empty() => Self::SendFile(PathBuf::from(rest)),
+            "/get" => Self::Get(parse_hash(rest)?),
+            "/image" if !rest.is_empty() => Self::Image(PathBuf::from(rest)),
+            "/edit" => match rest.split_once(char::is_whitespace) {
+                Some((target, text)) if !text.trim().is_empty() => {
+                    Self::Edit(target.to_string(), text.trim().to_string())
+                }
+                _ => return Ok(None),
+            },
+            "/delete" if !rest.is_empty() => Self::Delete(rest.to_string()),
+            "/reply" => match rest.split_once(char::is_whitespace) {
+                Some((target, text)) if !text.trim().is_empty() => {
+                    Self::Reply(target.to_string(), text.trim().to_string())
+                }
+                _ => return Ok(None),
+            },
+            "/thread" if !rest.is_empty() => Self::Thread(rest.to_string()),
+            "/react" => match rest.split_once(char::is_whitespace) {
+                Some((target, emoji)) if !emoji.trim().is_empty() => {
+                    Self::React(target.to_string(), emoji.trim().to_string())
+                }
+                _ => return Ok(None),
+            },
+            "/seen" if !rest.is_empty() => Self::Seen(rest.to_string()),
+            "/nick" => match rest.split_once(char::is_whitespace) {
+                Some((peer, nickname)) if !nickname.trim().is_empty() => {
+                    Self::Nick(peer.to_string(), nickname.trim().to_string())
+                }
+                _ => return Ok(None),
+            },
+            "/verify" => match rest.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [peer] => Self::Verify(peer.to_string(), None),
+                [peer, "ok"] => Self::Verify(peer.to_string(), Some(true)),
+                [peer, "no"] => Self::Verify(peer.to_string(), Some(false)),
+                _ => return Ok(None),
+            },
+            "/block" if !rest.is_empty() => Self::Block(rest.to_string(), true),
+            "/unblock" if !rest.is_empty() => Self::Block(rest.to_string(), false),
+            "/mute" if !rest.is_empty() => Self::Mute(rest.to_string(), true),
+            "/unmute" if !rest.is_empty() => Self::Mute(rest.to_string(), false),
+            "/add" if !rest.is_empty() => Self::AddMember(rest.to_string()),
+            "/remove" if !rest.is_empty() => Self::RemoveMember(rest.to_string()),
+            "/promote" if !rest.is_empty() => Self::Promote(rest.to_string()),
+            "/members" => Self::Members,
+            "/dm" => match rest.split_once(char::is_whitespace) {
+                Some((peer, text)) if !text.trim().is_empty() => {
+                    Self::Direct(peer.to_string(), text.trim().to_string())
+                }
+                _ => return Ok(None),
+            },
+            "/stats" => Self::Stats,
+            "/exit" => Self::Exit,
+            _ => return Err(CommandError::Unknown(action.to_string())),
+        };
+
+        Ok(Some(command))
+    }
+
+    /// Interpreta uma linha do stdin no modo jsonl.
+    pub fn parse_json(line: &str) -> Result<Option<Self>, CommandError> {
+        let line = line.trim();
+
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        let command = serde_json::from_str::<JsonCommand>(line)?;
+
+        Ok(Some(command.try_into()?))
+    }
+}
+
+// endregion:    --- Command
+
+// region:       --- utils
+
+#[derive(Debug, ThisError)]
+#[error("{self:?}")]
+pub enum CommandError {
+    Unknown(String),
+    InvalidHash(String),
+
+    // externals
+    Json(#[from] JsonError),
+}
+
+pub fn parse_hash(s: &str) -> Result<Hash, CommandError> {
+    // o `FromStr` do iroh-blobs entra em pânico com tamanhos diferentes de hex/base32
+    if s.len() != 64 && s.len() != 52 {
+        return Err(CommandError::InvalidHash(s.to_string()));
+    }
+
+    s.parse()
+        .map_err(|_| CommandError::InvalidHash(s.to_string()))
+}
+
+// endregion:    --- utils
@@ -1,6 +1,6 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use iroh::{NodeId, SecretKey};
@@ -21,6 +21,60 @@ pub fn friends_path() -> PathBuf {
     home
 }
 
+pub fn blocked_path() -> PathBuf {
+    let mut home = dirs::home_dir().expect("HOME não encontrado");
+    home.push(".chat-p2p");
+    std::fs::create_dir_all(&home).expect("não deu pra criar ~/.chat-p2p");
+    home.push("blocked");
+    home
+}
+
+pub fn muted_path() -> PathBuf {
+    let mut home = dirs::home_dir().expect("HOME não encontrado");
+    home.push(".chat-p2p");
+    std::fs::create_dir_all(&home).expect("não deu pra criar ~/.chat-p2p");
+    home.push("muted");
+    home
+}
+
+pub fn rooms_path() -> PathBuf {
+    let mut home = dirs::home_dir().expect("HOME não encontrado");
+    home.push(".chat-p2p");
+    home.push("rooms");
+    std::fs::create_dir_all(&home).expect("não deu pra criar ~/.chat-p2p/rooms");
+    home
+}
+
+/// As sessões de mensagens diretas de uma identidade: duas seeds na mesma
+/// máquina não podem dividir a sessão com um mesmo par.
+pub fn sessions_path(me: &NodeId) -> PathBuf {
+    let mut home = dirs::home_dir().expect("HOME não encontrado");
+    home.push(".chat-p2p");
+    home.push("sessions");
+    home.push(base58::encode(me).into_string());
+    std::fs::create_dir_all(&home).expect("não deu pra criar ~/.chat-p2p/sessions");
+    home
+}
+
+/// Um blob store por identidade: o store trava o diretório, e duas seeds na
+/// mesma máquina precisam abrir cada uma o seu.
+pub fn blobs_path(me: &NodeId) -> PathBuf {
+    let mut home = dirs::home_dir().expect("HOME não encontrado");
+    home.push(".chat-p2p");
+    home.push("blobs");
+    home.push(base58::encode(me).into_string());
+    std::fs::create_dir_all(&home).expect("não deu pra criar ~/.chat-p2p/blobs");
+    home
+}
+
+pub fn downloads_path() -> PathBuf {
+    let mut home = dirs::home_dir().expect("HOME não encontrado");
+    home.push(".chat-p2p");
+    home.push("downloads");
+    std::fs::create_dir_all(&home).expect("não deu pra criar ~/.chat-p2p/downloads");
+    home
+}
+
 pub fn load_salt() -> Result<[u8; 32]> {
     let path = key_path();
     if !path.exists() {
@@ -50,14 +104,25 @@ pub fn generate_secret_key(seed: &str) -> Result<SecretKey> {
     Ok(SecretKey::from_bytes(hash.as_bytes()))
 }
 
-pub fn load_friends() -> Result<BTreeSet<NodeId>> {
+/// Um amigo do arquivo `friends`, com o apelido que demos a ele.
+#[derive(Debug, Clone, Default)]
+pub struct Friend {
+    pub nickname: Option<String>,
+    /// Se já comparamos o número de segurança com ele, pelo `/verify`.
+    pub verified: bool,
+}
+
+/// Lê `~/.chat-p2p/friends`: uma linha por amigo, o id em base58 e,
+/// opcionalmente, um apelido depois de um espaço. Um `+` antes do id marca o
+/// amigo como verificado (o `+` não existe no alfabeto base58).
+pub fn load_friends() -> Result<BTreeMap<NodeId, Friend>> {
     let path = friends_path();
-    let mut friends = BTreeSet::new();
+    let mut friends = BTreeMap::new();
 
     if !path.exists() {
         fs::File::create(&path)?;
 
-        return Ok(BTreeSet::new());
+        return Ok(friends);
     }
 
     let existing = fs::read_to_string(&path)?;
@@ -66,26 +131,55 @@ pub fn load_friends() -> Result<BTreeSet<NodeId>> {
         if line.is_empty() || line.starts_with('#') {
             continue;
         }
-        let encoded = line.as_bytes();
-        let decoded = base58::decode(encoded).into_array_const()?;
-
-        friends.insert(NodeId::from_bytes(&decoded)?);
+        let (encoded, nickname) = match line.split_once(char::is_whitespace) {
+            Some((encoded, nickname)) => (encoded, Some(nickname.trim().to_string())),
+            None => (line, None),
+        };
+        let (encoded, verified) = match encoded.strip_prefix('+') {
+            Some(encoded) => (encoded, true),
+            None => (encoded, false),
+        };
+        let decoded = base58::decode(encoded.as_bytes()).into_array_const()?;
+        let friend = Friend { nickname, verified };
+
+        friends.insert(NodeId::from_bytes(&decoded)?, friend);
     }
 
     Ok(friends)
 }
 
+pub fn save_friends(friends: &BTreeMap<NodeId, Friend>) -> Result<()> {
+    let buf: String = friends
+        .iter()
+        .map(|(node, friend)| {
+            let mut line = String::new();
+            if friend.verified {
+                line.push('+');
+            }
+            line.push_str(&base58::encode(node).into_string());
+            if let Some(nickname) = &friend.nickname {
+                line.push(' ');
+                line.push_str(nickname);
+            }
+            line.push('\n');
+            line
+        })
+        .collect();
+
+    fs::write(friends_path(), buf)?;
+
+    Ok(())
+}
+
 pub fn load_friends_without_me(me: NodeId) -> Result<Vec<NodeId>> {
     let mut friends = load_friends()?;
 
     friends.remove(&me);
 
-    Ok(friends.into_iter().collect())
+    Ok(friends.into_keys().collect())
 }
 
 pub fn add_friends(new_friends_raw: &Vec<String>) -> Result<()> {
-    let path = friends_path();
-
     let mut friends = load_friends()?;
 
     for s in new_friends_raw {
@@ -94,12 +188,55 @@ pub fn add_friends(new_friends_raw: &Vec<String>) -> Result<()> {
             let encoded = s.as_bytes();
             let decoded = base58::decode(encoded).into_array_const()?;
 
-            friends.insert(NodeId::from_bytes(&decoded)?);
+            friends.entry(NodeId::from_bytes(&decoded)?).or_default();
         }
     }
 
-    let buf: String = friends
-        .into_iter()
+    save_friends(&friends)
+}
+
+/// Altera e grava um amigo. Retorna `false` se o par não é amigo.
+///
+/// Não adiciona ninguém: o arquivo também é a lista de bootstrap do gossip e
+/// a de quem entra nas salas `--private`, então um apelido não pode abrir a
+/// porta para um estranho.
+pub fn update_friend(actor: NodeId, update: impl FnOnce(&mut Friend)) -> Result<bool> {
+    let mut friends = load_friends()?;
+    let Some(friend) = friends.get_mut(&actor) else {
+        return Ok(false);
+    };
+
+    update(friend);
+    save_friends(&friends)?;
+
+    Ok(true)
+}
+
+/// Lê uma lista de ids em base58, um por linha, como `blocked` e `muted`.
+pub fn load_ids(path: &Path) -> Result<BTreeSet<NodeId>> {
+    let mut ids = BTreeSet::new();
+
+    if !path.exists() {
+        return Ok(ids);
+    }
+
+    let existing = fs::read_to_string(path)?;
+    for line in existing.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let decoded = base58::decode(line.as_bytes()).into_array_const()?;
+
+        ids.insert(NodeId::from_bytes(&decoded)?);
+    }
+
+    Ok(ids)
+}
+
+pub fn save_ids(path: &Path, ids: &BTreeSet<NodeId>) -> Result<()> {
+    let buf: String = ids
+        .iter()
         .map(|node| {
             let mut line = base58::encode(node).into_string();
             line.push('\n');
@@ -107,7 +244,7 @@ pub fn add_friends(new_friends_raw: &Vec<String>) -> Result<()> {
         })
         .collect();
 
-    fs::write(&path, buf)?;
+    fs::write(path, buf)?;
 
     Ok(())
 }
@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, anyhow, bail};
+use ed25519_dalek::SigningKey;
+use iroh::endpoint::Connection;
+use iroh::protocol::{AcceptError, ProtocolHandler};
+use iroh::{Endpoint, NodeId};
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+
+use crate::chat_event::MAX_TEXT_BYTES;
+use crate::config::sessions_path;
+use crate::files::Output;
+use crate::history::History;
+use crate::moderation::Moderation;
+use crate::ratchet::{self, Message, Session};
+use crate::sanitize;
+
+/// O ALPN das mensagens diretas, separado do gossip e dos blobs.
+pub const ALPN: &[u8] = b"chat-p2p/direct/0";
+
+/// O maior quadro aceito num stream; sobra espaço para o cabeçalho e a tag.
+const MAX_FRAME_BYTES: usize = MAX_TEXT_BYTES + 1024;
+
+// region:       --- structs
+
+/// Mensagens diretas entre dois pares, fora do tópico, cifradas com um
+/// double ratchet (ver `ratchet::Session`).
+///
+/// Cada quadro vai num stream bidirecional próprio e recebe uma resposta.
+/// Quem tem o id menor começa a sessão; o outro lado, se ainda não recebeu
+/// nada, pede com um `Hello` e recebe de volta uma mensagem vazia, que abre a
+/// cadeia de envio dele. Quem começa só troca de sessão por um `Hello` se a
+/// dele ainda não mandou nada; senão o outro lado espera a próxima mensagem,
+/// que o faz recomeçar. As sessões ficam em `~/.chat-p2p/sessions/<nosso
+/// id>`, uma por par.
+pub struct Direct {
+    endpoint: Endpoint,
+    key: SigningKey,
+    sessions: Mutex<HashMap<NodeId, Session>>,
+    history: Arc<Mutex<History>>,
+    moderation: Arc<Mutex<Moderation>>,
+    out: Output,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Frame {
+    /// Pede uma sessão nova a quem começa.
+    Hello,
+    Message(Message),
+    /// A mensagem foi aberta.
+    Ack,
+    /// A mensagem não abriu; quem mandou deve recomeçar a sessão.
+    Reset,
+}
+
+// endregion:    --- structs
+
+// region:       --- Direct impl
+
+impl Direct {
+    pub fn new(
+        endpoint: Endpoint,
+        key: SigningKey,
+        history: Arc<Mutex<History>>,
+        moderation: Arc<Mutex<Moderation>>,
+        out: Output,
+    ) -> Self {
+        Self {
+            endpoint,
+            key,
+            sessions: Mutex::new(HashMap::new()),
+            history,
+            moderation,
+            out,
+        }
+    }
+
+    /// Manda `text` para `peer`, recomeçando a sessão uma vez se ele não
+    /// conseguir abrir.
+    pub async fn send(&self, peer: NodeId, text: &str) -> Result<()> {
+        let conn = self.endpoint.connect(peer, ALPN).await?;
+        let result = match self.try_send(&conn, peer, text).await? {
+            true => Ok(()),
+            false => {
+                self.reset(peer)?;
+
+                match self.try_send(&conn, peer, text).await? {
+                    true => Ok(()),
+                    false => Err(anyhow!("{} could not open the message", short(&peer))),
+                }
+            }
+        };
+
+        conn.close(0u32.into(), b"done");
+
+        result
+    }
+
+    async fn try_send(&self, conn: &Connection, peer: NodeId, text: &str) -> Result<bool> {
+        if !self.with_session(peer, |session| session.can_send())? {
+            let message = match request(conn, &Frame::Hello).await? {
+                Frame::Message(message) => message,
+                Frame::Reset => bail!(
+                    "{} is still on an older session; it restarts with their next message",
+                    short(&peer)
+                ),
+                frame => bail!("unexpected {frame:?}"),
+            };
+
+            // a resposta é vazia; só serve para abrir a cadeia de envio
+            self.open(peer, &message)?;
+        }
+
+        let ad = ratchet::associated_data(&self.key, &peer);
+        let message =
+            self.with_session(peer, |session| session.encrypt(text.as_bytes(), &ad))??;
+
+        match request(conn, &Frame::Message(message)).await? {
+            Frame::Ack => Ok(true),
+            Frame::Reset => Ok(false),
+            frame => bail!("unexpected {frame:?}"),
+        }
+    }
+
+    /// Responde a um quadro do par.
+    fn handle(&self, peer: NodeId, frame: Frame) -> Result<Frame> {
+        match frame {
+            Frame::Hello if Session::starts(&self.key, &peer) => {
+                // o que já mandamos nessa sessão ainda pode estar a caminho
+                if !self.with_session(peer, |session| session.is_unused())? {
+                    return Ok(Frame::Reset);
+                }
+
+                self.reset(peer)?;
+
+                let ad = ratchet::associated_data(&self.key, &peer);
+                let message = self.with_session(peer, |session| session.encrypt(&[], &ad))??;
+
+                Ok(Frame::Message(message))
+            }
+            Frame::Message(message) => {
+                let Ok(text) = self.open(peer, &message) else {
+                    return Ok(Frame::Reset);
+                };
+
+                if !text.is_empty() {
+                    self.print(peer, &text)?;
+                }
+
+                Ok(Frame::Ack)
+            }
+            Frame::Hello | Frame::Ack | Frame::Reset => Ok(Frame::Reset),
+        }
+    }
+
+    /// Decifra uma mensagem do par. Quem responde também tenta uma sessão
+    /// nova, para o caso de quem começa ter perdido a dele.
+    fn open(&self, peer: NodeId, message: &Message) -> Result<String> {
+        let ad = ratchet::associated_data(&self.key, &peer);
+        let plaintext = match self.with_session(peer, |session| session.decrypt(message, &ad))? {
+            Ok(plaintext) => plaintext,
+            Err(_) if !Session::starts(&self.key, &peer) => {
+                let mut fresh = Session::responder(&self.key, &peer);
+                let plaintext = fresh.decrypt(message, &ad)?;
+
+                self.replace(peer, fresh)?;
+
+                plaintext
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    fn print(&self, peer: NodeId, text: &str) -> Result<()> {
+        if self.moderation.lock().unwrap().is_muted(&peer) {
+            return Ok(());
+        }
+
+        let label = {
+            let history = self.history.lock().unwrap();
+            let name = history.names().get(&peer).cloned().unwrap_or_default();
+
+            history.label(&peer, &name)
+        };
+
+        writeln!(
+            self.out.lock().unwrap(),
+            "{} {label}: {}",
+            "[dm]".magenta(),
+            sanitize::indent(&sanitize::text(text))
+        )?;
+
+        Ok(())
+    }
+
+    /// Roda `f` na sessão com o par, carregando do disco (ou criando) se
+    /// preciso, e grava o resultado.
+    fn with_session<T>(&self, peer: NodeId, f: impl FnOnce(&mut Session) -> T) -> Result<T> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = match sessions.entry(peer) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(load(&self.key, &peer)?),
+        };
+        let result = f(session);
+
+        save(&self.key, &peer, session)?;
+
+        Ok(result)
+    }
+
+    /// Joga fora a sessão com o par e começa outra.
+    fn reset(&self, peer: NodeId) -> Result<()> {
+        self.replace(peer, fresh(&self.key, &peer))
+    }
+
+    fn replace(&self, peer: NodeId, session: Session) -> Result<()> {
+        save(&self.key, &peer, &session)?;
+        self.sessions.lock().unwrap().insert(peer, session);
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Direct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Direct")
+            .field("endpoint", &self.endpoint.node_id())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ProtocolHandler for Direct {
+    async fn accept(&self, conn: Connection) -> Result<(), AcceptError> {
+        let peer = conn.remote_node_id()?;
+
+        // um quadro por stream, até quem conectou fechar
+        while let Ok((mut send, mut recv)) = conn.accept_bi().await {
+            let frame = recv
+                .read_to_end(MAX_FRAME_BYTES)
+                .await
+                .map_err(AcceptError::from_err)?;
+            let reply = match postcard::from_bytes(&frame) {
+                Ok(frame) => self.handle(peer, frame),
+                Err(_) => Ok(Frame::Reset),
+            };
+            let reply = reply.unwrap_or(Frame::Reset);
+            let reply = postcard::to_allocvec(&reply).map_err(AcceptError::from_err)?;
+
+            send.write_all(&reply)
+                .await
+                .map_err(AcceptError::from_err)?;
+            send.finish().map_err(AcceptError::from_err)?;
+        }
+
+        Ok(())
+    }
+}
+
+// endregion:    --- Direct impl
+
+// region:       --- utils
+
+async fn request(conn: &Connection, frame: &Frame) -> Result<Frame> {
+    let (mut send, mut recv) = conn.open_bi().await?;
+
+    send.write_all(&postcard::to_allocvec(frame)?).await?;
+    send.finish()?;
+
+    let reply = recv.read_to_end(MAX_FRAME_BYTES).await?;
+
+    Ok(postcard::from_bytes(&reply)?)
+}
+
+fn fresh(key: &SigningKey, peer: &NodeId) -> Session {
+    if Session::starts(key, peer) {
+        Session::initiator(key, peer)
+    } else {
+        Session::responder(key, peer)
+    }
+}
+
+fn session_path(key: &SigningKey, peer: &NodeId) -> PathBuf {
+    let mut path = sessions_path(&NodeId::from(key.verifying_key()));
+    path.push(base58::encode(peer).into_string());
+    path
+}
+
+fn load(key: &SigningKey, peer: &NodeId) -> Result<Session> {
+    let path = session_path(key, peer);
+
+    if !path.exists() {
+        return Ok(fresh(key, peer));
+    }
+
+    Ok(postcard::from_bytes(&std::fs::read(path)?)?)
+}
+
+fn save(key: &SigningKey, peer: &NodeId, session: &Session) -> Result<()> {
+    std::fs::write(session_path(key, peer), postcard::to_allocvec(session)?)?;
+
+    Ok(())
+}
+
+fn short(peer: &NodeId) -> String {
+    base58::encode(peer).into_string()[..5].to_string()
+}
+
+// endregion:    --- utils
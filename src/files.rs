@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result, bail};
+use futures_lite::StreamExt;
+use iroh::{Endpoint, NodeId};
+use iroh_blobs::api::downloader::{DownloadProgessItem, Downloader};
+use iroh_blobs::store::fs::FsStore;
+use iroh_blobs::{BlobsProtocol, Hash};
+use tokio::sync::broadcast;
+
+use crate::chat_event::ChatEvent;
+use crate::config::{blobs_path, downloads_path};
+
+/// Saída compartilhada para avisos locais (progresso, erros de comando).
+pub type Output = Arc<Mutex<dyn Write + Send>>;
+
+// region:       --- structs
+
+/// Arquivos compartilhados no tópico, guardados num blob store endereçado por conteúdo.
+pub struct Files {
+    store: FsStore,
+    downloader: Downloader,
+    known: Mutex<HashMap<Hash, SharedFile>>,
+}
+
+#[derive(Debug, Clone)]
+struct SharedFile {
+    provider: NodeId,
+    name: String,
+    size: u64,
+}
+
+// endregion:    --- structs
+
+// region:       --- Files impl
+
+impl Files {
+    pub async fn load(endpoint: &Endpoint) -> Result<Self> {
+        let store = FsStore::load(blobs_path(&endpoint.node_id())).await?;
+        let downloader = store.downloader(endpoint);
+
+        Ok(Self {
+            store,
+            downloader,
+            known: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// O protocolo que serve os nossos blobs para quem pedir.
+    pub fn protocol(&self, endpoint: &Endpoint) -> BlobsProtocol {
+        BlobsProtocol::new(&self.store, endpoint.clone(), None)
+    }
+
+    /// Importa um arquivo local e devolve nome, tamanho e hash para o anúncio.
+    pub async fn import(&self, path: &Path) -> Result<(String, u64, Hash)> {
+        let path = std::path::absolute(path)?;
+        let size = std::fs::metadata(&path)?.len();
+        let name = path
+            .file_name()
+            .context("path has no file name")?
+            .to_string_lossy()
+            .into_owned();
+        let tag = self.store.blobs().add_path(&path).await?;
+
+        Ok((name, size, tag.hash))
+    }
+
+    /// Acompanha os anúncios de arquivo para saber de quem baixar cada hash.
+    pub fn spawn_tracker(self: Arc<Self>, mut events: broadcast::Receiver<ChatEvent>) {
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                if let ChatEvent::FileShared {
+                    actor,
+                    name,
+                    size,
+                    hash,
+                } = event
+                {
+                    let file = SharedFile {
+                        provider: actor,
+                        name,
+                        size,
+                    };
+
+                    self.known.lock().unwrap().insert(hash, file);
+                }
+            }
+        });
+    }
+
+    /// Baixa o blob direto de quem anunciou e exporta para
+    /// `~/.chat-p2p/downloads`.
+    ///
+    /// O iroh-blobs já confere cada pedaço contra a árvore BLAKE3 do hash
+    /// durante o download; o que chega no store é o que foi anunciado.
+    pub async fn fetch(&self, hash: Hash, out: Output) -> Result<PathBuf> {
+        let Some(file) = self.known.lock().unwrap().get(&hash).cloned() else {
+            bail!("unknown file {hash}");
+        };
+
+        let mut progress = self
+            .downloader
+            .download(hash, Some(file.provider))
+            .stream()
+            .await?;
+        let mut last_percent = 0;
+
+        while let Some(item) = progress.next().await {
+            match item {
+                DownloadProgessItem::Progress(bytes) => {
+                    let percent = (bytes * 100).checked_div(file.size).unwrap_or(100);
+
+                    if percent >= last_percent + 10 {
+                        last_percent = percent;
+                        writeln!(out.lock().unwrap(), "{}: {percent}%", file.name)?;
+                    }
+                }
+                DownloadProgessItem::Error(e) => return Err(e),
+                DownloadProgessItem::DownloadError => bail!("download of {hash} failed"),
+                _ => {}
+            }
+        }
+
+        // o nome vem do par, então só aproveitamos o último componente
+        let file_name = Path::new(&file.name)
+            .file_name()
+            .map_or_else(|| hash.to_hex(), |name| name.to_string_lossy().into_owned());
+        let target = unused_path(&downloads_path(), &file_name);
+
+        self.store.blobs().export(hash, &target).await?;
+
+        Ok(target)
+    }
+}
+
+// endregion:    --- Files impl
+
+// region:       --- utils
+
+/// `dir/name`, ou `dir/name (1)`, `dir/name (2)`... com o número antes da
+/// extensão, o primeiro que ainda não existe. Um download não apaga outro.
+fn unused_path(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+
+    if !path.exists() {
+        return path;
+    }
+
+    let name = Path::new(name);
+    let stem = name
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let extension = name
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()));
+
+    (1..)
+        .map(|n| {
+            dir.join(format!(
+                "{stem} ({n}){}",
+                extension.as_deref().unwrap_or("")
+            ))
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+// endregion:    --- utils
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downloads_do_not_overwrite() {
+        let dir = std::env::temp_dir().join(format!("chat-p2p-{}", rand::random::<u64>()));
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(unused_path(&dir, "foto.png"), dir.join("foto.png"));
+
+        std::fs::write(dir.join("foto.png"), b"1").unwrap();
+        std::fs::write(dir.join("foto (1).png"), b"2").unwrap();
+        std::fs::write(dir.join("notas"), b"3").unwrap();
+
+        assert_eq!(unused_path(&dir, "foto.png"), dir.join("foto (2).png"));
+        assert_eq!(unused_path(&dir, "notas"), dir.join("notas (1)"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use crypto_box::aead::Aead;
+use crypto_box::{ChaChaBox, PublicKey, SecretKey};
+use ed25519_dalek::SigningKey;
+use iroh::NodeId;
+use iroh_gossip::api::GossipSender;
+use iroh_gossip::proto::TopicId;
+
+use crate::chat_event::{Envelope, RoomContext, SealedKey, SignedChatEvent};
+use crate::moderation::Moderation;
+
+/// Contexto do `blake3::derive_key` da chave do modo selado.
+const SEALED_CONTEXT: &str = "chat-p2p 2025-09 sealed sender";
+
+/// Chave simétrica de uma época da sala.
+pub type GroupKey = [u8; 32];
+
+/// Chave do modo selado, a partir do segredo combinado entre os membros.
+///
+/// O tópico entra junto, para o mesmo segredo não abrir outra sala. Não há
+/// nada que atrase tentativas: o segredo precisa ser longo.
+pub fn sealed_key(secret: &str, topic: &TopicId) -> GroupKey {
+    let mut material = topic.as_bytes().to_vec();
+
+    material.extend_from_slice(secret.as_bytes());
+
+    blake3::derive_key(SEALED_CONTEXT, &material)
+}
+
+// region:       --- seal
+
+/// Sela `key` para cada membro, com a chave X25519 derivada do id dele.
+///
+/// É a mesma conversão ed25519 → X25519 que o iroh usa: não precisa trocar
+/// chave nenhuma além do id que já está na lista de membros.
+pub fn seal(members: &BTreeSet<NodeId>, from: &SigningKey, key: &GroupKey) -> Vec<SealedKey> {
+    let secret = SecretKey::from(from.to_scalar());
+
+    members
+        .iter()
+        .filter_map(|member| {
+            let public = PublicKey::from(member.public().to_montgomery());
+            let nonce = rand::random::<[u8; 24]>();
+            let sealed = ChaChaBox::new(&public, &secret)
+                .encrypt(&nonce.into(), key.as_slice())
+                .ok()?;
+
+            Some(SealedKey {
+                member: *member,
+                nonce,
+                sealed,
+            })
+        })
+        .collect()
+}
+
+/// Abre a nossa cópia da chave selada por `from`, se houver uma.
+pub fn open(keys: &[SealedKey], from: &NodeId, me: &SigningKey) -> Option<GroupKey> {
+    let my_id = NodeId::from(me.verifying_key());
+    let mine = keys.iter().find(|sealed| sealed.member == my_id)?;
+    let public = PublicKey::from(from.public().to_montgomery());
+    let secret = SecretKey::from(me.to_scalar());
+    let key = ChaChaBox::new(&public, &secret)
+        .decrypt(&mine.nonce.into(), mine.sealed.as_slice())
+        .ok()?;
+
+    key.try_into().ok()
+}
+
+// endregion:    --- seal
+
+// region:       --- Outbox
+
+/// O sender do tópico, cifrando com a chave atual quando a sala tem uma.
+///
+/// Operações de sala saem abertas; o resto sai na última época. Quem ainda
+/// não recebeu a chave não publica nada, para não vazar texto aberto numa
+/// sala cifrada. No modo selado, tudo ainda vai dentro de um `Envelope`.
+#[derive(Clone)]
+pub struct Outbox {
+    sender: GossipSender,
+    room: RoomContext,
+    moderation: Arc<Mutex<Moderation>>,
+    sealed: Option<GroupKey>,
+}
+
+impl Outbox {
+    pub fn new(
+        sender: GossipSender,
+        room: RoomContext,
+        moderation: Arc<Mutex<Moderation>>,
+    ) -> Self {
+        Self {
+            sender,
+            room,
+            moderation,
+            sealed: None,
+        }
+    }
+
+    /// O contexto para assinar e verificar os eventos desta sala.
+    pub fn room(&self) -> RoomContext {
+        self.room
+    }
+
+    /// Fecha cada evento num `Envelope` com esta chave (ver `sealed_key`).
+    pub fn sealed(mut self, key: Option<GroupKey>) -> Self {
+        self.sealed = key;
+        self
+    }
+
+    /// Publica o evento. Retorna `false` sem publicar se a sala tem chave e
+    /// a nossa ainda não chegou.
+    pub async fn broadcast(&self, event: &SignedChatEvent) -> Result<bool> {
+        let current = if event.is_room_op() {
+            None
+        } else {
+            let moderation = self.moderation.lock().unwrap();
+
+            moderation
+                .room()
+                .and_then(|room| room.epoch().map(|epoch| (epoch, room.key(epoch))))
+        };
+        let event = match current {
+            None => event.clone(),
+            Some((epoch, Some(key))) => event.clone().encrypt(epoch, &key),
+            Some((_, None)) => return Ok(false),
+        };
+
+        self.sender
+            .broadcast(self.wrap(event.to_vec()).into())
+            .await?;
+
+        Ok(true)
+    }
+
+    /// Manda um evento já serializado só para os vizinhos diretos.
+    pub async fn broadcast_neighbors(&self, raw: Vec<u8>) -> Result<()> {
+        self.sender
+            .broadcast_neighbors(self.wrap(raw).into())
+            .await?;
+
+        Ok(())
+    }
+
+    fn wrap(&self, raw: Vec<u8>) -> Vec<u8> {
+        match &self.sealed {
+            Some(key) => Envelope::seal(&raw, key).to_vec(),
+            None => raw,
+        }
+    }
+}
+
+// endregion:    --- Outbox
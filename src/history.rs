@@ -0,0 +1,652 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use iroh::NodeId;
+use owo_colors::OwoColorize;
+
+use crate::chat_event::{ChatEvent, MessageId, ReceiptKind, actor_rbg, author, short_id};
+use crate::config::Friend;
+use crate::{mention, sanitize};
+
+/// Quantas mensagens ficam guardadas antes de descartar as mais antigas.
+const MAX_MESSAGES: usize = 10_000;
+
+/// Tamanho máximo, em bytes, de uma reação.
+const MAX_EMOJI_BYTES: usize = 32;
+
+/// Até que profundidade `/thread` recua as respostas; as mais fundas ficam
+/// nessa coluna.
+const MAX_THREAD_DEPTH: usize = 16;
+
+// region:       --- structs
+
+/// Mensagens vistas nesta sessão, para resolver ids e aplicar edições.
+#[derive(Debug, Default)]
+pub struct History {
+    messages: HashMap<MessageId, Entry>,
+    order: VecDeque<MessageId>,
+    names: HashMap<NodeId, String>,
+    receipts: HashMap<NodeId, Watermark>,
+    next_seq: u64,
+    friends: BTreeMap<NodeId, Friend>,
+}
+
+/// Até onde um par confirmou ter recebido e lido, em números de sequência locais.
+#[derive(Debug, Default, Clone, Copy)]
+struct Watermark {
+    delivered: u64,
+    read: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: MessageId,
+    /// Ordem de chegada nesta sessão, começando em 1.
+    pub seq: u64,
+    pub actor: NodeId,
+    pub name: String,
+    pub text: String,
+    pub reply_to: Option<MessageId>,
+    pub edited: bool,
+    pub deleted: bool,
+    pub reactions: BTreeMap<String, BTreeSet<NodeId>>,
+}
+
+// endregion:    --- structs
+
+// region:       --- History impl
+
+impl History {
+    /// Registra o evento. Retorna `false` quando ele deve ser ignorado, como
+    /// uma edição de quem não é o autor ou de uma mensagem desconhecida.
+    pub fn apply(&mut self, event: &ChatEvent) -> bool {
+        match event {
+            ChatEvent::NewMessage {
+                actor,
+                id,
+                name,
+                message,
+                reply_to,
+            } => {
+                if self.messages.contains_key(id) {
+                    return false;
+                }
+
+                self.set_name(*actor, name);
+
+                if self.order.len() >= MAX_MESSAGES
+                    && let Some(oldest) = self.order.pop_front()
+                {
+                    self.messages.remove(&oldest);
+                }
+
+                self.next_seq += 1;
+
+                let entry = Entry {
+                    id: *id,
+                    seq: self.next_seq,
+                    actor: *actor,
+                    name: name.clone(),
+                    text: message.clone(),
+                    reply_to: *reply_to,
+                    edited: false,
+                    deleted: false,
+                    reactions: BTreeMap::new(),
+                };
+
+                self.messages.insert(*id, entry);
+                self.order.push_back(*id);
+
+                true
+            }
+            ChatEvent::Edit {
+                actor,
+                target,
+                new_text,
+            } => match self.messages.get_mut(target) {
+                Some(entry) if entry.actor == *actor && !entry.deleted => {
+                    entry.text = new_text.clone();
+                    entry.edited = true;
+
+                    true
+                }
+                _ => false,
+            },
+            ChatEvent::Delete { actor, target } => match self.messages.get_mut(target) {
+                Some(entry) if entry.actor == *actor && !entry.deleted => {
+                    entry.deleted = true;
+                    entry.text.clear();
+
+                    true
+                }
+                _ => false,
+            },
+            ChatEvent::React {
+                actor,
+                target,
+                emoji,
+            } => {
+                let valid = !emoji.is_empty()
+                    && emoji.len() <= MAX_EMOJI_BYTES
+                    && !emoji
+                        .chars()
+                        .any(|c| c.is_whitespace() || sanitize::is_unsafe(c));
+
+                match self.messages.get_mut(target) {
+                    Some(entry) if valid && !entry.deleted => {
+                        let actors = entry.reactions.entry(emoji.clone()).or_default();
+
+                        // reagir de novo com o mesmo emoji desfaz a reação
+                        if !actors.remove(actor) {
+                            actors.insert(*actor);
+                        }
+                        if actors.is_empty() {
+                            entry.reactions.remove(emoji);
+                        }
+
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            ChatEvent::Receipt { actor, kind, up_to } => {
+                let Some(seq) = self.messages.get(up_to).map(|entry| entry.seq) else {
+                    return false;
+                };
+                let watermark = self.receipts.entry(*actor).or_default();
+
+                // ler implica ter recebido
+                watermark.delivered = watermark.delivered.max(seq);
+                if *kind == ReceiptKind::Read {
+                    watermark.read = watermark.read.max(seq);
+                }
+
+                true
+            }
+            ChatEvent::SetName { actor, name } => {
+                self.set_name(*actor, name);
+
+                true
+            }
+            _ => true,
+        }
+    }
+
+    /// O último nome visto de cada ator.
+    pub fn names(&self) -> &HashMap<NodeId, String> {
+        &self.names
+    }
+
+    pub fn set_name(&mut self, actor: NodeId, name: &str) {
+        if name.trim().is_empty() {
+            self.names.entry(actor).or_default();
+        } else {
+            self.names.insert(actor, name.trim().to_string());
+        }
+    }
+
+    pub fn set_friends(&mut self, friends: BTreeMap<NodeId, Friend>) {
+        self.friends = friends;
+    }
+
+    /// Altera um amigo, como `config::update_friend` faz no arquivo.
+    pub fn update_friend(&mut self, actor: NodeId, update: impl FnOnce(&mut Friend)) {
+        if let Some(friend) = self.friends.get_mut(&actor) {
+            update(friend);
+        }
+    }
+
+    pub fn is_friend(&self, actor: &NodeId) -> bool {
+        self.friends.contains_key(actor)
+    }
+
+    /// Nome para mostrar: o apelido que demos, o último nome visto ou, sem
+    /// nenhum dos dois, o começo do id.
+    pub fn display_name(&self, actor: &NodeId) -> String {
+        if let Some(nickname) = self.nickname(actor) {
+            return nickname.to_string();
+        }
+
+        match self.names.get(actor) {
+            Some(name) if !name.is_empty() => sanitize::line(name),
+            _ => base58::encode(actor).into_string()[..5].to_string(),
+        }
+    }
+
+    /// Rótulo do autor de uma linha: amigos ganham o apelido, se houver, e `★`
+    /// quando verificados ou `☆` quando não; quem usa um nome parecido com o de
+    /// outro par ganha `⚠`.
+    pub fn label(&self, actor: &NodeId, name: &str) -> String {
+        if !self.is_friend(actor) {
+            let mut label = author(actor, name);
+
+            if self.confusable_with(actor, name).is_some() {
+                label.push_str(&format!(" {}", "⚠".yellow()));
+            }
+
+            return label;
+        }
+
+        let badge = if self
+            .friends
+            .get(actor)
+            .is_some_and(|friend| friend.verified)
+        {
+            "★".green().to_string()
+        } else {
+            "☆".yellow().to_string()
+        };
+        let name = match self.nickname(actor) {
+            // o apelido é nosso, então vai sem as aspas dos nomes escolhidos pelo par
+            Some(nickname) => nickname.to_string(),
+            None => {
+                let name = sanitize::line(name);
+
+                if name.trim().is_empty() {
+                    return format!("{} {badge}", short_id(actor));
+                }
+
+                format!("\"{}\"", name.trim())
+            }
+        };
+
+        let (r, g, b) = actor_rbg(actor);
+
+        format!("{} {badge} {}", short_id(actor), name.truecolor(r, g, b))
+    }
+
+    /// Outro par cujo nome (ou apelido) se confunde com `name`, comparando os
+    /// esqueletos Unicode.
+    pub fn confusable_with(&self, actor: &NodeId, name: &str) -> Option<NodeId> {
+        let target = skeleton(name);
+
+        if target.is_empty() {
+            return None;
+        }
+
+        let claimed = self
+            .names
+            .iter()
+            .map(|(other, name)| (other, name.as_str()));
+        let nicknames = self
+            .friends
+            .iter()
+            .filter_map(|(other, friend)| Some((other, friend.nickname.as_deref()?)));
+
+        nicknames
+            .chain(claimed)
+            .find(|(other, name)| *other != actor && skeleton(name) == target)
+            .map(|(other, _)| *other)
+    }
+
+    /// A linha de um evento com o rótulo de autor de `label`.
+    pub fn render_event(&self, event: &ChatEvent) -> String {
+        match event {
+            ChatEvent::NewMessage { actor, name, .. } | ChatEvent::Image { actor, name, .. } => {
+                event.with_author(&self.label(actor, name))
+            }
+            _ => event.to_string(),
+        }
+    }
+
+    /// Encontra um par por id em base58 (ou prefixo dele), nome ou apelido.
+    /// Só resolve quando há exatamente um candidato.
+    pub fn resolve_actor(&self, token: &str) -> Option<NodeId> {
+        let token = token.trim_start_matches('@');
+
+        if let Ok(decoded) = base58::decode(token.as_bytes()).into_array_const::<32>()
+            && let Ok(actor) = NodeId::from_bytes(&decoded)
+        {
+            return Some(actor);
+        }
+
+        let mut found = mention::resolve(token, &self.names);
+
+        found.extend(
+            self.friends
+                .iter()
+                .filter(|(_, friend)| {
+                    friend
+                        .nickname
+                        .as_deref()
+                        .is_some_and(|nickname| nickname.eq_ignore_ascii_case(token))
+                })
+                .map(|(actor, _)| *actor),
+        );
+
+        match found.len() {
+            1 => found.pop_first(),
+            _ => None,
+        }
+    }
+
+    fn nickname(&self, actor: &NodeId) -> Option<&str> {
+        self.friends.get(actor)?.nickname.as_deref()
+    }
+
+    pub fn get(&self, id: &MessageId) -> Option<&Entry> {
+        self.messages.get(id)
+    }
+
+    /// Encontra a mensagem mais recente cujo id começa com `prefix`.
+    pub fn resolve(&self, prefix: &str) -> Option<MessageId> {
+        let prefix = prefix.trim_start_matches('#');
+
+        if prefix.is_empty() {
+            return None;
+        }
+
+        self.order
+            .iter()
+            .rev()
+            .find(|id| id.to_string().starts_with(prefix))
+            .copied()
+    }
+
+    /// A mensagem mais recente de alguém que não seja `me`.
+    pub fn latest_from_others(&self, me: NodeId) -> Option<MessageId> {
+        self.order
+            .iter()
+            .rev()
+            .find(|id| self.messages.get(id).is_some_and(|entry| entry.actor != me))
+            .copied()
+    }
+
+    /// Quem já recebeu e quem já leu a mensagem, fora o autor.
+    pub fn receipts(&self, entry: &Entry) -> (Vec<NodeId>, Vec<NodeId>) {
+        let mut delivered = Vec::new();
+        let mut read = Vec::new();
+
+        for (actor, watermark) in &self.receipts {
+            if *actor == entry.actor {
+                continue;
+            }
+            if watermark.read >= entry.seq {
+                read.push(*actor);
+            } else if watermark.delivered >= entry.seq {
+                delivered.push(*actor);
+            }
+        }
+
+        (delivered, read)
+    }
+
+    /// A mensagem como aparece nas listagens, com marca de edição, reações e
+    /// ticks: ✓ quando alguém recebeu, ✓✓ quando alguém leu.
+    pub fn render(&self, entry: &Entry) -> String {
+        let mut line = self.render_event(&entry.to_event());
+
+        if entry.edited && !entry.deleted {
+            line.push_str(&format!(" {}", "(edited)".dimmed()));
+        }
+        for (emoji, actors) in &entry.reactions {
+            line.push_str(&format!(" {emoji} {}", actors.len()));
+        }
+
+        match self.receipts(entry) {
+            (_, read) if !read.is_empty() => line.push_str(&format!(" {}", "✓✓".dimmed())),
+            (delivered, _) if !delivered.is_empty() => line.push_str(&format!(" {}", "✓".dimmed())),
+            _ => {}
+        }
+
+        line
+    }
+
+    /// A mensagem `root` e todas as respostas a ela, com a profundidade de cada uma.
+    pub fn thread(&self, root: MessageId) -> Vec<(usize, &Entry)> {
+        let mut thread = Vec::new();
+        let Some(entry) = self.messages.get(&root) else {
+            return thread;
+        };
+
+        // as respostas de cada mensagem, na ordem de chegada, numa passada só
+        let mut replies = HashMap::<MessageId, Vec<&Entry>>::new();
+
+        for reply in self.order.iter().filter_map(|id| self.messages.get(id)) {
+            if let Some(parent) = reply.reply_to {
+                replies.entry(parent).or_default().push(reply);
+            }
+        }
+
+        // uma pilha em vez de recursão: uma corrente longa de respostas não
+        // estoura a pilha
+        let mut stack = vec![(0, entry)];
+
+        while let Some((depth, entry)) = stack.pop() {
+            thread.push((depth.min(MAX_THREAD_DEPTH), entry));
+
+            if let Some(replies) = replies.get(&entry.id) {
+                stack.extend(replies.iter().rev().map(|reply| (depth + 1, *reply)));
+            }
+        }
+
+        thread
+    }
+}
+
+impl Entry {
+    /// O evento equivalente ao estado atual da mensagem, para reimprimir.
+    /// Apagada, ela aparece só como `(deleted)`.
+    pub fn to_event(&self) -> ChatEvent {
+        let message = match self.deleted {
+            true => "(deleted)".to_string(),
+            false => self.text.clone(),
+        };
+
+        ChatEvent::NewMessage {
+            actor: self.actor,
+            id: self.id,
+            name: self.name.clone(),
+            message,
+            reply_to: self.reply_to,
+        }
+    }
+
+    /// Começo do texto, para citar a mensagem numa resposta.
+    pub fn snippet(&self) -> String {
+        const MAX_CHARS: usize = 40;
+
+        if self.deleted {
+            return "(deleted)".to_string();
+        }
+
+        let mut snippet = self.text.chars().take(MAX_CHARS).collect::<String>();
+
+        if self.text.chars().count() > MAX_CHARS {
+            snippet.push('…');
+        }
+
+        snippet
+    }
+}
+
+// endregion:    --- History impl
+
+// region:       --- utils
+
+/// Forma canônica de um nome para comparação, pelo esqueleto de confusão do
+/// Unicode (UTS #39). Passa duas vezes, antes e depois das minúsculas, para
+/// `B0B`, `aIice` e `Вob` com В cirílico colidirem com `bob` e `alice`.
+fn skeleton(name: &str) -> String {
+    let name = sanitize::line(name);
+    let lower = unicode_security::skeleton(name.trim())
+        .collect::<String>()
+        .to_lowercase();
+
+    unicode_security::skeleton(&lower).collect()
+}
+
+// endregion:    --- utils
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use iroh_gossip::proto::TopicId;
+
+    use super::*;
+    use crate::chat_event::{ChatEventBody, RoomContext};
+
+    fn peer() -> (SigningKey, NodeId) {
+        let key = SigningKey::from_bytes(&rand::random());
+        let id = NodeId::from(key.verifying_key());
+
+        (key, id)
+    }
+
+    fn message(key: &SigningKey, text: &str) -> ChatEvent {
+        reply(key, text, None)
+    }
+
+    fn reply(key: &SigningKey, text: &str, reply_to: Option<MessageId>) -> ChatEvent {
+        let context = RoomContext {
+            topic: TopicId::from_bytes([7; 32]),
+            work: 0,
+            accept_legacy: false,
+        };
+
+        // sem carimbo, a assinatura nem chega a esperar
+        let (_, event) = futures_lite::future::block_on(
+            ChatEvent::builder(context)
+                .body(ChatEventBody::NewMessage {
+                    name: "alice".to_string(),
+                    message: text.to_string(),
+                    reply_to,
+                })
+                .sign_with_echo(key),
+        );
+
+        event
+    }
+
+    fn id(event: &ChatEvent) -> MessageId {
+        match event {
+            ChatEvent::NewMessage { id, .. } => *id,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn only_the_author_edits_and_deletes() {
+        let ((alice_key, alice), (_, bob)) = (peer(), peer());
+        let mut history = History::default();
+        let event = message(&alice_key, "oi");
+        let target = id(&event);
+
+        assert!(history.apply(&event));
+        assert!(!history.apply(&ChatEvent::Edit {
+            actor: bob,
+            target,
+            new_text: "tchau".to_string(),
+        }));
+        assert!(history.apply(&ChatEvent::Edit {
+            actor: alice,
+            target,
+            new_text: "olá".to_string(),
+        }));
+
+        let entry = history.get(&target).unwrap();
+
+        assert_eq!(entry.text, "olá");
+        assert!(entry.edited);
+        assert!(history.render(entry).contains("(edited)"));
+
+        assert!(!history.apply(&ChatEvent::Delete { actor: bob, target }));
+        assert!(history.apply(&ChatEvent::Delete {
+            actor: alice,
+            target,
+        }));
+
+        // apagada, não volta por edição nem aparece na listagem
+        assert!(!history.apply(&ChatEvent::Edit {
+            actor: alice,
+            target,
+            new_text: "de novo".to_string(),
+        }));
+
+        let entry = history.get(&target).unwrap();
+        let line = history.render(entry);
+
+        assert!(line.contains("(deleted)"));
+        assert!(!line.contains("olá") && !line.contains("(edited)"));
+        assert_eq!(entry.snippet(), "(deleted)");
+    }
+
+    #[test]
+    fn reacting_twice_undoes_the_reaction() {
+        let ((alice_key, alice), (_, bob)) = (peer(), peer());
+        let mut history = History::default();
+        let event = message(&alice_key, "oi");
+        let target = id(&event);
+        let react = |actor, emoji: &str| ChatEvent::React {
+            actor,
+            target,
+            emoji: emoji.to_string(),
+        };
+
+        history.apply(&event);
+
+        assert!(history.apply(&react(alice, "👍")));
+        assert!(history.apply(&react(bob, "👍")));
+        assert_eq!(history.get(&target).unwrap().reactions["👍"].len(), 2);
+
+        assert!(history.apply(&react(bob, "👍")));
+        assert_eq!(history.get(&target).unwrap().reactions["👍"].len(), 1);
+
+        assert!(history.apply(&react(alice, "👍")));
+        assert!(history.get(&target).unwrap().reactions.is_empty());
+
+        // nem vazio, nem com espaço, nem com controle
+        assert!(!history.apply(&react(bob, "")));
+        assert!(!history.apply(&react(bob, "a b")));
+        assert!(!history.apply(&react(bob, "\u{1b}[2J")));
+
+        history.apply(&ChatEvent::Delete {
+            actor: alice,
+            target,
+        });
+
+        assert!(!history.apply(&react(bob, "👍")));
+    }
+
+    #[test]
+    fn threads_follow_reply_order_and_cap_the_depth() {
+        let (key, _) = peer();
+        let mut history = History::default();
+        let root = message(&key, "raiz");
+        let first = reply(&key, "primeira", Some(id(&root)));
+        let second = reply(&key, "segunda", Some(id(&root)));
+        let nested = reply(&key, "dentro", Some(id(&first)));
+
+        for event in [&root, &first, &second, &nested] {
+            history.apply(event);
+        }
+
+        let thread = history
+            .thread(id(&root))
+            .into_iter()
+            .map(|(depth, entry)| (depth, entry.text.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            thread,
+            [(0, "raiz"), (1, "primeira"), (2, "dentro"), (1, "segunda")]
+        );
+
+        // uma corrente funda não estoura a pilha nem a tela
+        let mut parent = id(&root);
+
+        for _ in 0..5_000 {
+            let event = reply(&key, "mais", Some(parent));
+
+            parent = id(&event);
+            history.apply(&event);
+        }
+
+        let thread = history.thread(id(&root));
+
+        assert_eq!(thread.len(), 5_004);
+        assert_eq!(
+            thread.iter().map(|(depth, _)| *depth).max(),
+            Some(MAX_THREAD_DEPTH)
+        );
+    }
+}
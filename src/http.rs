@@ -0,0 +1,122 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use ed25519_dalek::SigningKey;
+use futures_lite::Stream;
+use serde::Deserialize;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+
+use crate::chat_event::{ChatEvent, MAX_NAME_BYTES, MAX_TEXT_BYTES};
+use crate::group_key::Outbox;
+use crate::jsonl::JsonEvent;
+
+// region:       --- structs
+
+/// O que a ponte HTTP precisa para falar no tópico em nome deste nó.
+#[derive(Clone)]
+pub struct HttpState {
+    pub sender: Outbox,
+    pub bus: broadcast::Sender<ChatEvent>,
+    pub key: SigningKey,
+    pub topic: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PostMessage {
+    message: String,
+    name: Option<String>,
+}
+
+// endregion:    --- structs
+
+// region:       --- server
+
+/// Sobe o servidor HTTP em `addr`.
+///
+/// - `POST /rooms/<topic>/messages` com `{"message": "...", "name": "..."}`
+///   assina e publica uma mensagem;
+/// - `GET /rooms/<topic>/events` transmite os eventos verificados via SSE.
+pub async fn serve(addr: SocketAddr, state: HttpState) -> Result<()> {
+    let app = Router::new()
+        .route("/rooms/{topic}/messages", post(post_message))
+        .route("/rooms/{topic}/events", get(stream_events))
+        .with_state(state);
+    let listener = TcpListener::bind(addr).await?;
+
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn post_message(
+    State(state): State<HttpState>,
+    Path(topic): Path<String>,
+    Json(body): Json<PostMessage>,
+) -> StatusCode {
+    if topic != state.topic {
+        return StatusCode::NOT_FOUND;
+    }
+    if body.message.trim().is_empty() {
+        return StatusCode::BAD_REQUEST;
+    }
+
+    let name = body.name.unwrap_or(state.name);
+
+    if name.len() > MAX_NAME_BYTES || body.message.len() > MAX_TEXT_BYTES {
+        return StatusCode::PAYLOAD_TOO_LARGE;
+    }
+
+    let (event, local) = ChatEvent::builder(state.sender.room())
+        .new_message(name, body.message)
+        .sign_with_echo(&state.key)
+        .await;
+
+    match state.sender.broadcast(&event).await {
+        Ok(true) => {}
+        // a sala tem chave e a nossa ainda não chegou
+        Ok(false) => return StatusCode::CONFLICT,
+        Err(_) => return StatusCode::SERVICE_UNAVAILABLE,
+    }
+
+    let _ = state.bus.send(local);
+
+    StatusCode::ACCEPTED
+}
+
+async fn stream_events(
+    State(state): State<HttpState>,
+    Path(topic): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>, StatusCode> {
+    if topic != state.topic {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let events = state.bus.subscribe();
+    let stream =
+        futures_lite::stream::unfold((events, state.topic), |(mut events, topic)| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        let json = JsonEvent::new(&event, &topic);
+                        let sse = SseEvent::default().event(json.kind()).data(json.to_line());
+
+                        return Some((Ok(sse), (events, topic)));
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// endregion:    --- server
@@ -0,0 +1,199 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use iroh::NodeId;
+use serde::{Deserialize, Serialize};
+
+use crate::chat_event::ChatEvent;
+use crate::command::{Command, CommandError, parse_hash};
+
+// region:       --- structs
+
+/// Evento verificado como é impresso no modo `--format jsonl`.
+#[derive(Debug, Serialize)]
+pub struct JsonEvent<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    actor: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    timestamp: u64,
+    topic: &'a str,
+}
+
+/// Comando aceito no stdin no modo `--format jsonl`, uma linha por objeto.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JsonCommand {
+    Message { message: String },
+    SetName { name: String },
+    Join,
+    Leave,
+    SendFile { path: String },
+    Get { hash: String },
+    Image { path: String },
+    Edit { target: String, new_text: String },
+    Delete { target: String },
+    Reply { target: String, message: String },
+    React { target: String, emoji: String },
+    Exit,
+}
+
+// endregion:    --- structs
+
+// region:       --- impls
+
+impl<'a> JsonEvent<'a> {
+    pub fn new(event: &'a ChatEvent, topic: &'a str) -> Self {
+        let mut json = Self::ready(event.actor(), topic);
+
+        match event {
+            ChatEvent::NewMessage {
+                id,
+                name,
+                message,
+                reply_to,
+                ..
+            } => {
+                json.kind = "new_message";
+                json.id = Some(id.to_string());
+                json.target = reply_to.map(|id| id.to_string());
+                json.name = Some(name);
+                json.message = Some(message);
+            }
+            ChatEvent::SetName { name, .. } => {
+                json.kind = "set_name";
+                json.name = Some(name);
+            }
+            ChatEvent::NodeJoined { .. } => json.kind = "node_joined",
+            ChatEvent::NodeLeft { .. } => json.kind = "node_left",
+            ChatEvent::FileShared {
+                name, size, hash, ..
+            } => {
+                json.kind = "file_shared";
+                json.name = Some(name);
+                json.size = Some(*size);
+                json.hash = Some(hash.to_string());
+            }
+            ChatEvent::Image { name, .. } => {
+                json.kind = "image";
+                json.name = Some(name);
+            }
+            ChatEvent::Edit {
+                target, new_text, ..
+            } => {
+                json.kind = "edit";
+                json.target = Some(target.to_string());
+                json.message = Some(new_text);
+            }
+            ChatEvent::Delete { target, .. } => {
+                json.kind = "delete";
+                json.target = Some(target.to_string());
+            }
+            ChatEvent::React { target, emoji, .. } => {
+                json.kind = "react";
+                json.target = Some(target.to_string());
+                json.message = Some(emoji);
+            }
+            ChatEvent::Typing { .. } => json.kind = "typing",
+            ChatEvent::AddMember { member, .. } => {
+                json.kind = "add_member";
+                json.target = Some(base58::encode(member).into_string());
+            }
+            ChatEvent::RemoveMember { member, .. } => {
+                json.kind = "remove_member";
+                json.target = Some(base58::encode(member).into_string());
+            }
+            ChatEvent::Promote { member, .. } => {
+                json.kind = "promote";
+                json.target = Some(base58::encode(member).into_string());
+            }
+            ChatEvent::RoomKey { .. } => json.kind = "room_key",
+            ChatEvent::Receipt { kind, up_to, .. } => {
+                json.kind = "receipt";
+                json.target = Some(up_to.to_string());
+                json.message = Some(kind.as_str());
+            }
+        }
+
+        json
+    }
+
+    /// Primeira linha impressa, para o script saber o próprio id.
+    pub fn ready(actor: NodeId, topic: &'a str) -> Self {
+        Self {
+            kind: "ready",
+            actor: base58::encode(actor).into_string(),
+            id: None,
+            target: None,
+            name: None,
+            message: None,
+            size: None,
+            hash: None,
+            timestamp: now_millis(),
+            topic,
+        }
+    }
+
+    /// Um comando recusado, devolvido a quem o mandou.
+    pub fn error(actor: NodeId, topic: &'a str, message: &'a str) -> Self {
+        Self {
+            kind: "error",
+            message: Some(message),
+            ..Self::ready(actor, topic)
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl TryFrom<JsonCommand> for Command {
+    type Error = CommandError;
+
+    fn try_from(command: JsonCommand) -> Result<Self, Self::Error> {
+        let command = match command {
+            JsonCommand::Message { message } => Command::Send(message),
+            JsonCommand::SetName { name } => Command::Name(name),
+            JsonCommand::Join => Command::Join,
+            JsonCommand::Leave => Command::Leave,
+            JsonCommand::SendFile { path } => Command::SendFile(path.into()),
+            JsonCommand::Get { hash } => Command::Get(parse_hash(&hash)?),
+            JsonCommand::Image { path } => Command::Image(path.into()),
+            JsonCommand::Edit { target, new_text } => Command::Edit(target, new_text),
+            JsonCommand::Delete { target } => Command::Delete(target),
+            JsonCommand::Reply { target, message } => Command::Reply(target, message),
+            JsonCommand::React { target, emoji } => Command::React(target, emoji),
+            JsonCommand::Exit => Command::Exit,
+        };
+
+        Ok(command)
+    }
+}
+
+// endregion:    --- impls
+
+// region:       --- utils
+
+/// Milissegundos desde a época unix, usado como horário de recebimento.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+// endregion:    --- utils
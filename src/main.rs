@@ -1,25 +1,66 @@
 #![allow(unused_variables)]
 
 mod chat_event;
+mod command;
 mod config;
-
+mod direct;
+mod files;
+mod group_key;
+mod history;
+mod http;
+mod jsonl;
+mod markdown;
+mod mention;
+mod moderation;
+mod plugin;
+mod preview;
+mod ratchet;
+mod rate_limit;
+mod receipts;
+mod room;
+mod safety;
+mod sanitize;
+mod session;
+mod typing;
+
+use std::collections::HashSet;
 use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Result, bail};
 use clap::builder::Styles;
+use clap::{Parser, ValueEnum};
 use futures_lite::StreamExt;
-use iroh::Endpoint;
 use iroh::protocol::Router;
+use iroh::{Endpoint, NodeId};
 use iroh_gossip::api::{Event, GossipReceiver};
 use iroh_gossip::net::Gossip;
 use iroh_gossip::proto::TopicId;
 use owo_colors::OwoColorize;
 use rustyline_async::{Readline, ReadlineEvent, SharedWriter};
-use {base58, postcard};
-
-use crate::chat_event::{ChatEvent, SignedChatEvent};
-use crate::config::{add_friends, generate_secret_key, load_friends_without_me};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::broadcast;
+
+use crate::chat_event::{ChatEvent, Envelope, RoomContext, SignedChatEvent};
+use crate::command::{Command, CommandError};
+use crate::config::{
+    add_friends, generate_secret_key, load_friends, load_friends_without_me, load_ids,
+};
+use crate::direct::Direct;
+use crate::files::{Files, Output};
+use crate::group_key::{GroupKey, Outbox, sealed_key};
+use crate::history::History;
+use crate::http::HttpState;
+use crate::jsonl::JsonEvent;
+use crate::moderation::{Guard, Moderation};
+use crate::plugin::{ExternalPlugin, Plugin, PluginHost};
+use crate::preview::ImageMode;
+use crate::room::Room;
+use crate::session::Session;
+use crate::typing::{Input, TermWriter, WatchInput};
 
 #[derive(Parser, Debug)]
 #[clap(styles = Styles::plain())]
@@ -38,21 +79,145 @@ struct Args {
     /// Friends to add.
     #[clap(short = 'f', long, num_args = 1..)]
     friends: Vec<String>,
+
+    /// Executables to run as bots, speaking the jsonl format.
+    #[clap(short = 'p', long = "plugin")]
+    plugins: Vec<PathBuf>,
+
+    /// Serve the HTTP bridge on this address.
+    #[clap(long, value_name = "ADDR", num_args = 0..=1, default_missing_value = "127.0.0.1:8080")]
+    http: Option<SocketAddr>,
+
+    /// Allow the HTTP bridge to listen on non-loopback addresses.
+    #[clap(long, requires = "http")]
+    http_allow_remote: bool,
+
+    /// How to draw received images.
+    #[clap(long, value_enum, default_value_t = ImageMode::Off)]
+    images: ImageMode,
+
+    /// Command run through `sh -c` when someone mentions you.
+    #[clap(long, value_name = "COMMAND")]
+    notify: Option<String>,
+
+    /// Don't send delivery and read receipts.
+    #[clap(long)]
+    no_receipts: bool,
+
+    /// Only accept friends in this room.
+    #[clap(long)]
+    private: bool,
+
+    /// Only accept the ids listed in this file, one per line (implies --private).
+    #[clap(long, value_name = "PATH")]
+    members: Option<PathBuf>,
+
+    /// The room owner's id (or `me`); only members added by the owner and its admins can talk.
+    #[clap(long, value_name = "ID")]
+    owner: Option<String>,
+
+    /// Shared secret; events travel sealed, hiding their authors from anyone without it.
+    #[clap(long, value_name = "SECRET")]
+    sealed: Option<String>,
+
+    /// Events per second each peer may send, on average, before being ignored for a minute.
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t = rate_limit::DEFAULT_RATE,
+        value_parser = rate_limit::parse_rate,
+    )]
+    rate: f64,
+
+    /// Events a peer may send in a row before --rate applies.
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t = rate_limit::DEFAULT_BURST,
+        value_parser = rate_limit::parse_burst,
+    )]
+    burst: f64,
+
+    /// Largest event accepted from the room, in bytes; bigger ones are dropped unread.
+    #[clap(
+        long,
+        value_name = "BYTES",
+        default_value_t = chat_event::DEFAULT_MAX_EVENT_BYTES as u64,
+        value_parser = clap::value_parser!(u64).range(1024..=iroh_gossip::proto::DEFAULT_MAX_MESSAGE_SIZE as u64),
+    )]
+    max_event_bytes: u64,
+
+    /// Proof-of-work bits every event must carry; everyone in the room must agree.
+    #[clap(long, value_name = "BITS", default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=32))]
+    work: u8,
+
+    /// Also accept events in the old, unversioned format, signed without the room context.
+    #[clap(long)]
+    accept_legacy: bool,
+
+    /// Input and output format.
+    #[clap(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+/// O que o `print_loop` precisa saber para desenhar os eventos.
+#[derive(Debug, Clone)]
+struct PrintOptions {
+    format: Format,
+    topic: String,
+    me: NodeId,
+    images: ImageMode,
+    notify: Option<String>,
+    moderation: Arc<Mutex<Moderation>>,
+}
+
+const PROMPT: &str = "> ";
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// Colored text and an interactive prompt.
+    Text,
+    /// One JSON object per line on stdin and stdout.
+    Jsonl,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
 
+    if let Some(addr) = args.http
+        && !addr.ip().is_loopback()
+        && !args.http_allow_remote
+    {
+        bail!("refusing to serve HTTP on {addr} without --http-allow-remote");
+    }
+
     add_friends(&args.friends)?;
     let topic = args.topic;
     let hash = blake3::hash(topic.as_bytes());
     let topic_id = TopicId::from_bytes(*hash.as_bytes());
+    let room = RoomContext {
+        topic: topic_id,
+        work: args.work,
+        accept_legacy: args.accept_legacy,
+    };
 
     let secret_key = generate_secret_key(args.seed.as_deref().unwrap_or(""))?;
     let public_key = secret_key.public();
 
-    let friends = load_friends_without_me(public_key)?;
+    let mut friends = load_friends_without_me(public_key)?;
+    let members = match &args.members {
+        Some(path) if !path.exists() => bail!("{} not found", path.display()),
+        Some(path) => Some(load_ids(path)?),
+        None => None,
+    };
+
+    // numa sala com lista própria, os membros também servem de entrada no gossip
+    if let Some(members) = &members {
+        friends.extend(members.iter().filter(|member| **member != public_key));
+        friends.sort();
+        friends.dedup();
+    }
 
     let endpoint = Endpoint::builder()
         .secret_key(secret_key)
@@ -61,135 +226,633 @@ async fn main() -> Result<()> {
         .await?;
 
     let gossip = Gossip::builder().spawn(endpoint.clone());
+    let files = Arc::new(Files::load(&endpoint).await?);
+
+    let mut moderation = Moderation::load()?;
+
+    moderation.set_limits(args.rate, args.burst);
 
+    if let Some(members) = members {
+        moderation.restrict_to(members);
+    } else if args.private {
+        moderation.restrict_to(load_friends()?.into_keys().collect());
+    }
+
+    if let Some(owner) = &args.owner {
+        let owner = match owner.as_str() {
+            "me" => endpoint.node_id(),
+            owner => {
+                let decoded = base58::decode(owner.as_bytes()).into_array_const()?;
+
+                NodeId::from_bytes(&decoded)?
+            }
+        };
+
+        let key = endpoint.secret_key().secret().clone();
+
+        moderation.set_room(Room::load(owner, room, key)?);
+    }
+
+    // nem como entrada no gossip
+    friends.retain(|friend| !moderation.is_blocked(friend));
+
+    let moderation = Arc::new(Mutex::new(moderation));
+    let (sender, receiver) = gossip.subscribe(topic_id, friends).await?.split();
+    let sealed = args
+        .sealed
+        .as_deref()
+        .map(|secret| sealed_key(secret, &topic_id));
+    let sender = Outbox::new(sender, room, moderation.clone()).sealed(sealed);
+    let (bus, _) = broadcast::channel(1024);
+
+    tokio::spawn(receive_loop(
+        receiver,
+        sender.clone(),
+        bus.clone(),
+        moderation.clone(),
+        sealed,
+        args.max_event_bytes as usize,
+    ));
+    files.clone().spawn_tracker(bus.subscribe());
+
+    let seed = args.seed.unwrap_or_default();
+    let plugins = args
+        .plugins
+        .iter()
+        .map(|path| Box::new(ExternalPlugin::new(path)) as Box<dyn Plugin>)
+        .collect::<Vec<_>>();
+
+    PluginHost::new(plugins, &topic, room, |name| {
+        let key = generate_secret_key(&format!("{seed}/plugin/{name}"))?;
+
+        Ok(key.secret().clone())
+    })?
+    .spawn(sender.clone(), bus.clone());
+
+    let key = endpoint.secret_key().secret().clone();
+    let name = args.name.unwrap_or_default();
+
+    if let Some(addr) = args.http {
+        let state = HttpState {
+            sender: sender.clone(),
+            bus: bus.clone(),
+            key: key.clone(),
+            topic: topic.clone(),
+            name: name.clone(),
+        };
+
+        tokio::spawn(async move {
+            if let Err(e) = http::serve(addr, state).await {
+                eprintln!("[http] {e}");
+            }
+        });
+    }
+
+    let (readline, out): (_, Output) = match args.format {
+        Format::Text => {
+            let (rl, stdout) = Readline::new(PROMPT.to_string())?;
+            let written = Arc::new(AtomicU64::new(0));
+            let stdout = TermWriter::new(stdout, written.clone());
+
+            (
+                Some((rl, stdout.clone(), written)),
+                Arc::new(Mutex::new(stdout)),
+            )
+        }
+        Format::Jsonl => (None, Arc::new(Mutex::new(std::io::stderr()))),
+    };
+
+    let history = Arc::new(Mutex::new(History::default()));
+    let options = PrintOptions {
+        format: args.format,
+        topic: topic.clone(),
+        me: endpoint.node_id(),
+        images: args.images,
+        notify: args.notify,
+        moderation: moderation.clone(),
+    };
+
+    {
+        let mut history = history.lock().unwrap();
+
+        history.set_name(options.me, &name);
+        history.set_friends(load_friends()?);
+    }
+
+    let direct = Arc::new(Direct::new(
+        endpoint.clone(),
+        key.clone(),
+        history.clone(),
+        moderation.clone(),
+        out.clone(),
+    ));
+    // bloqueados (e, em sala privada, quem não é membro) nem conectam
     let router = Router::builder(endpoint.clone())
-        .accept(iroh_gossip::ALPN, gossip.clone())
+        .accept(
+            iroh_gossip::ALPN,
+            Guard::new(gossip.clone(), moderation.clone()),
+        )
+        .accept(
+            iroh_blobs::ALPN,
+            Guard::new(files.protocol(&endpoint), moderation.clone()),
+        )
+        .accept(direct::ALPN, Guard::new(direct.clone(), moderation.clone()))
         .spawn();
 
-    let (mut rl, mut stdout) = Readline::new("> ".to_string())?;
+    if !args.no_receipts {
+        receipts::spawn_delivered(bus.subscribe(), sender.clone(), key.clone(), options.me);
+    }
 
+    // o dono abre a primeira época; as seguintes saem a cada mudança de membros
+    let needs_key = moderation
+        .lock()
+        .unwrap()
+        .room()
+        .is_some_and(|room| room.owner() == options.me && room.epoch().is_none());
+    let mut session = Session::new(sender, bus.clone(), key, name, files, history.clone(), out)
+        .receipts(!args.no_receipts)
+        .moderation(moderation)
+        .direct(direct);
+
+    if needs_key {
+        session.rotate_key().await?;
+    }
+
+    match readline {
+        Some((rl, stdout, written)) => {
+            text_loop(&mut session, rl, stdout, &written, &bus, &history, options).await?
+        }
+        None => jsonl_loop(&mut session, &bus, &history, options).await?,
+    }
+
+    router.shutdown().await?;
+
+    Ok(())
+}
+
+async fn text_loop(
+    session: &mut Session,
+    mut rl: Readline,
+    mut stdout: TermWriter<SharedWriter>,
+    written: &AtomicU64,
+    bus: &broadcast::Sender<ChatEvent>,
+    history: &Arc<Mutex<History>>,
+    options: PrintOptions,
+) -> Result<()> {
     rl.should_print_line_on(false, false);
     rl.clear()?;
 
-    writeln!(stdout, "{}", topic)?;
-    writeln!(stdout, "{}", base58::encode(public_key).into_string())?;
+    writeln!(stdout, "{}", options.topic)?;
+    writeln!(stdout, "{}", base58::encode(session.actor()).into_string())?;
+
+    let mut status = typing::spawn_status(
+        bus.subscribe(),
+        history.clone(),
+        options.moderation.clone(),
+        options.me,
+    );
+
+    tokio::spawn(print_loop(
+        bus.subscribe(),
+        history.clone(),
+        stdout.clone(),
+        options,
+    ));
+
+    loop {
+        let input = tokio::select! {
+            // o status vem primeiro para não confundir o despertar dele com tecla
+            biased;
+            Ok(()) = status.changed() => None,
+            input = WatchInput::new(rl.readline(), written) => Some(input),
+        };
 
-    let (sender, receiver) = gossip.subscribe(topic_id, friends).await?.split();
+        let line_event = match input {
+            None => {
+                let status = status.borrow_and_update().clone();
+                let prompt = if status.is_empty() {
+                    PROMPT.to_string()
+                } else {
+                    format!("{status} {PROMPT}")
+                };
 
-    tokio::spawn(subscribe_loop(receiver, stdout.clone()));
+                rl.update_prompt(&prompt)?;
 
-    let key = endpoint.secret_key().secret();
-    let mut name = args.name.unwrap_or_default();
+                continue;
+            }
+            Some(Input::Edited) => {
+                session.typing();
 
-    while let Ok(line_event) = rl.readline().await {
+                continue;
+            }
+            Some(Input::Done(Ok(line_event))) => line_event,
+            Some(Input::Done(Err(_))) => break,
+        };
         let ReadlineEvent::Line(line) = line_event else {
             break;
         };
-        let line = line.trim();
-
-        let event = if line.starts_with("/") {
-            let (action, rest) = match line.split_once(char::is_whitespace) {
-                Some((cmd, rest)) => (cmd, rest.trim()),
-                None => (line, ""),
-            };
-
-            match action {
-                "/send" => {
-                    if rest.is_empty() {
-                        continue;
-                    }
 
-                    let message_event = ChatEvent::NewMessage {
-                        actor: endpoint.node_id(),
-                        name: name.clone(),
-                        message: rest.to_string(),
-                    };
+        let command = match Command::parse(&line) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(CommandError::Unknown(action)) => {
+                writeln!(stdout, "unknown action {action}")?;
 
-                    writeln!(stdout, "{}", message_event.bold())?;
+                continue;
+            }
+            Err(e) => {
+                writeln!(stdout, "{e}")?;
 
-                    rl.add_history_entry(rest.to_string());
+                continue;
+            }
+        };
 
-                    ChatEvent::builder().new_message(&name, rest).sign(key)
-                }
-                "/name" => {
-                    // writeln!(stdout, "{name} -> {rest}")?;
+        match &command {
+            Command::Exit => break,
+            Command::Send(message) => {
+                rl.add_history_entry(message.clone());
+            }
+            _ => {}
+        }
+
+        session.execute(command).await?;
+    }
+
+    Ok(())
+}
+
+async fn jsonl_loop(
+    session: &mut Session,
+    bus: &broadcast::Sender<ChatEvent>,
+    history: &Arc<Mutex<History>>,
+    options: PrintOptions,
+) -> Result<()> {
+    let mut stdout = std::io::stdout();
+
+    writeln!(
+        stdout,
+        "{}",
+        JsonEvent::ready(session.actor(), &options.topic).to_line()
+    )?;
+
+    tokio::spawn(print_loop(
+        bus.subscribe(),
+        history.clone(),
+        std::io::stdout(),
+        options,
+    ));
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let command = match Command::parse_json(&line) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("invalid command: {e}");
+
+                continue;
+            }
+        };
+
+        if let Command::Exit = command {
+            break;
+        }
+
+        session.execute(command).await?;
+    }
+
+    Ok(())
+}
 
-                    name = rest.to_string();
+/// Verifica os eventos recebidos do gossip e publica no barramento.
+async fn receive_loop(
+    mut receiver: GossipReceiver,
+    sender: Outbox,
+    bus: broadcast::Sender<ChatEvent>,
+    moderation: Arc<Mutex<Moderation>>,
+    sealed: Option<GroupKey>,
+    max_event_bytes: usize,
+) -> Result<()> {
+    while let Some(gossip_event) = receiver.try_next().await? {
+        match gossip_event {
+            Event::Received(gossip_message) => {
+                // antes de qualquer parse, para um par não nos fazer alocar à toa
+                if gossip_message.content.len() > max_event_bytes {
+                    moderation
+                        .lock()
+                        .unwrap()
+                        .limiter_mut()
+                        .stats_mut()
+                        .oversized += 1;
 
                     continue;
                 }
-                "/join" => ChatEvent::builder().node_joined().sign(key),
-                "/leave" => ChatEvent::builder().node_left().sign(key),
-                "/exit" => break,
-                _ => {
-                    writeln!(stdout, "unknown action {action}")?;
+
+                let Some((raw, event)) = open_event(
+                    &gossip_message.content,
+                    sealed.as_ref(),
+                    &sender.room(),
+                    &moderation,
+                ) else {
+                    moderation.lock().unwrap().limiter_mut().stats_mut().invalid += 1;
 
                     continue;
+                };
+
+                let events = {
+                    let mut moderation = moderation.lock().unwrap();
+                    let actor = event.actor();
+
+                    if room::is_op(&event) {
+                        // admins não passam pelo limite só nas operações de sala, que
+                        // chegam de uma vez no log reenviado a cada vizinho novo
+                        let admin = moderation.room().is_some_and(|room| room.is_admin(&actor));
+
+                        if !admin && !moderation.limiter_mut().allow(actor) {
+                            continue;
+                        }
+
+                        // sem dono não há log. Quem assinou ainda pode não ser
+                        // membro aqui (a promoção dele está a caminho), então a
+                        // operação vai para a sala, que decide ou guarda
+                        let Some(room) = moderation.room_mut() else {
+                            continue;
+                        };
+                        let events = match room.insert(raw, event) {
+                            Ok(events) => events,
+                            Err(e) => {
+                                eprintln!("[room] {e}");
+
+                                continue;
+                            }
+                        };
+
+                        // quem saiu da sala não fica conectado
+                        moderation.close_blocked();
+
+                        events
+                    } else {
+                        if moderation.is_blocked(&actor) {
+                            moderation.limiter_mut().stats_mut().blocked += 1;
+
+                            continue;
+                        }
+
+                        if !moderation.limiter_mut().allow(actor) {
+                            continue;
+                        }
+
+                        vec![event]
+                    }
+                };
+
+                for event in events {
+                    let _ = bus.send(event);
                 }
             }
-        } else {
-            if line.is_empty() {
-                continue;
+            Event::NeighborUp(_) => {
+                let raws = moderation
+                    .lock()
+                    .unwrap()
+                    .room()
+                    .map(Room::raw_ops)
+                    .unwrap_or_default();
+
+                // quem chegou não viu o log; só os vizinhos diretos precisam dele
+                for raw in raws {
+                    sender.broadcast_neighbors(raw).await?;
+                }
             }
+            _ => {}
+        }
+    }
+    Ok(())
+}
 
-            let message_event = ChatEvent::NewMessage {
-                actor: endpoint.node_id(),
-                name: name.clone(),
-                message: line.to_string(),
-            };
-
-            writeln!(stdout, "{}", message_event.bold())?;
+/// Abre o envelope (no modo selado), decifra a época e verifica o carimbo e a
+/// assinatura.
+///
+/// Devolve o evento assinado como foi publicado, para o log da sala, e o
+/// evento verificado. `None` se qualquer passo falhar.
+fn open_event(
+    content: &[u8],
+    sealed: Option<&GroupKey>,
+    room: &RoomContext,
+    moderation: &Mutex<Moderation>,
+) -> Option<(Vec<u8>, ChatEvent)> {
+    let raw = match sealed {
+        // no modo selado, o que não abre com a chave não é da sala
+        Some(key) => postcard::from_bytes::<Envelope>(content)
+            .ok()?
+            .open(key)
+            .ok()?,
+        None => content.to_vec(),
+    };
+    let unverified_event = SignedChatEvent::decode(&raw).ok()?;
+    let epoch = unverified_event.epoch();
+    let (room_epoch, key) = {
+        let moderation = moderation.lock().unwrap();
+        let room = moderation.room();
+
+        (
+            room.and_then(Room::epoch),
+            room.zip(epoch).and_then(|(room, epoch)| room.key(epoch)),
+        )
+    };
+    let unverified_event = match (epoch, key) {
+        (None, _) => unverified_event,
+        (Some(_), Some(key)) => unverified_event.decrypt(&key).ok()?,
+        // época que não foi selada para nós: ou saímos, ou ainda não chegou
+        (Some(_), None) => return None,
+    };
+    let event = unverified_event.verify_into(room).ok()?;
+
+    // numa sala com chave, só as operações podem chegar abertas
+    if epoch.is_none() && room_epoch.is_some() && !room::is_op(&event) {
+        return None;
+    }
 
-            rl.add_history_entry(line.to_string());
+    Some((raw, event))
+}
 
-            ChatEvent::builder().new_message(&name, line).sign(key)
+async fn print_loop(
+    mut events: broadcast::Receiver<ChatEvent>,
+    history: Arc<Mutex<History>>,
+    mut stdout: impl Write,
+    options: PrintOptions,
+) -> Result<()> {
+    let PrintOptions {
+        format,
+        topic,
+        me,
+        images,
+        notify,
+        moderation,
+    } = options;
+    // quem já recebeu o aviso de nome parecido, para não repetir a cada linha
+    let mut warned = HashSet::new();
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
         };
 
-        sender.broadcast(event.to_vec().into()).await?;
-    }
+        if !history.lock().unwrap().apply(&event) {
+            continue;
+        }
 
-    router.shutdown().await?;
+        // silenciados continuam no histórico, só não aparecem
+        if moderation.lock().unwrap().is_muted(&event.actor()) {
+            continue;
+        }
 
-    Ok(())
-}
+        if format == Format::Jsonl {
+            if event.actor() != me {
+                writeln!(stdout, "{}", JsonEvent::new(&event, &topic).to_line())?;
+                stdout.flush()?;
+            }
 
-async fn subscribe_loop(mut receiver: GossipReceiver, mut stdout: SharedWriter) -> Result<()> {
-    while let Some(gossip_event) = receiver.try_next().await? {
-        if let Event::Received(gossip_message) = gossip_event {
-            let unverified_event =
-                postcard::from_bytes::<SignedChatEvent>(&gossip_message.content)?;
-            let Ok(event) = unverified_event.verify_into() else {
-                continue;
-            };
-            match &event {
-                ChatEvent::NewMessage {
-                    actor,
-                    name,
-                    message,
-                } => {
-                    writeln!(stdout, "{event}")?;
+            continue;
+        }
+
+        match &event {
+            ChatEvent::NewMessage {
+                actor,
+                name,
+                message,
+                reply_to,
+                ..
+            } => {
+                if let Some(parent) = reply_to {
+                    let quote = {
+                        let history = history.lock().unwrap();
+
+                        match history.get(parent) {
+                            Some(entry) => format!(
+                                "┌ {}: {}",
+                                history.display_name(&entry.actor),
+                                sanitize::line(&entry.snippet())
+                            ),
+                            None => format!("┌ #{}", parent.fmt_short()),
+                        }
+                    };
+
+                    writeln!(stdout, "{}", quote.dimmed())?;
                 }
-                ChatEvent::SetName { actor, name } => {
-                    todo!();
-                    // let prev_name = names.get(&actor).map_or_else(
-                    //     || actor.fmt_short(),
-                    //     |name| format!("{} \"{name}\"", actor.fmt_short()),
-                    // );
-
-                    // writeln!(
-                    //     stdout,
-                    //     "{prev_name} is now known as {} \"{name}\"",
-                    //     actor.fmt_short()
-                    // )?;
+
+                if *actor == me {
+                    let line = history.lock().unwrap().render_event(&event);
+
+                    writeln!(stdout, "{}", line.bold())?;
+
+                    continue;
+                }
+
+                let (line, names, mentioned, lookalike) = {
+                    let history = history.lock().unwrap();
+                    let names = history.names().clone();
+                    let mentioned = mention::mentioned(message, &names).contains(&me);
+                    let lookalike = if history.is_friend(actor) {
+                        None
+                    } else {
+                        history
+                            .confusable_with(actor, name)
+                            .map(|other| history.display_name(&other))
+                    };
+
+                    (history.render_event(&event), names, mentioned, lookalike)
+                };
+
+                if let Some(other) = lookalike
+                    && warned.insert(*actor)
+                {
+                    writeln!(
+                        stdout,
+                        "{} {} uses a name that looks like {other}'s",
+                        "⚠".yellow(),
+                        &base58::encode(actor).into_string()[..5],
+                    )?;
+                }
+
+                if !mentioned {
+                    writeln!(stdout, "{line}")?;
+
+                    continue;
+                }
+
+                // destaca depois do markdown, que removeria os escapes do destaque
+                let highlighted = mention::highlight(&line, me, &names);
+
+                writeln!(stdout, "{highlighted}\x07")?;
+
+                if let Some(command) = &notify {
+                    notify_mention(command, &topic, name, message);
                 }
-                ChatEvent::NodeJoined { actor } => {
-                    writeln!(stdout, "{event}")?;
+            }
+            ChatEvent::SetName { .. } => {
+                writeln!(stdout, "{event}")?;
+            }
+            ChatEvent::NodeJoined { actor } => {
+                writeln!(stdout, "{event}")?;
+            }
+            ChatEvent::NodeLeft { actor } => {
+                writeln!(stdout, "{event}")?;
+            }
+            ChatEvent::FileShared { .. } => {
+                writeln!(stdout, "{event}")?;
+            }
+            ChatEvent::Image { thumbnail, .. } => {
+                let line = history.lock().unwrap().render_event(&event);
+
+                writeln!(stdout, "{line}")?;
+
+                if images != ImageMode::Off
+                    && let Ok(rendered) = preview::render(thumbnail, images)
+                {
+                    writeln!(stdout, "{rendered}")?;
                 }
-                ChatEvent::NodeLeft { actor } => {
-                    writeln!(stdout, "{event}")?;
+            }
+            ChatEvent::Edit { target, .. } | ChatEvent::React { target, .. } => {
+                let line = {
+                    let history = history.lock().unwrap();
+
+                    history.get(target).map(|entry| history.render(entry))
+                };
+
+                if let Some(line) = line {
+                    writeln!(stdout, "{line}")?;
                 }
             }
+            ChatEvent::Delete { .. }
+            | ChatEvent::AddMember { .. }
+            | ChatEvent::RemoveMember { .. }
+            | ChatEvent::Promote { .. }
+            | ChatEvent::RoomKey { .. } => {
+                writeln!(stdout, "{event}")?;
+            }
+            // aparece no prompt, pelo `typing::spawn_status`
+            ChatEvent::Typing { .. } => {}
+            // aparece nos ticks e no `/seen`
+            ChatEvent::Receipt { .. } => {}
         }
     }
     Ok(())
 }
+
+/// Roda o comando de notificação sem esperar por ele.
+fn notify_mention(command: &str, topic: &str, name: &str, message: &str) {
+    let spawned = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("CHAT_P2P_TOPIC", topic)
+        .env("CHAT_P2P_NAME", name)
+        .env("CHAT_P2P_MESSAGE", message)
+        .spawn();
+
+    if let Err(e) = spawned {
+        eprintln!("[notify] {e}");
+    }
+}
@@ -0,0 +1,224 @@
+use owo_colors::OwoColorize;
+
+use crate::sanitize;
+
+const FENCE: &str = "```";
+
+// region:       --- render
+
+/// Desenha o texto de uma mensagem com um markdown simplificado:
+/// `*negrito*`, `_itálico_`, `` `código` ``, blocos entre ```` ``` ````,
+/// `[texto](url)`, links soltos e citações com `>`.
+///
+/// O texto passa antes por `sanitize::text`, que tira escapes e controles, e
+/// as linhas depois da primeira saem recuadas (ver `sanitize::indent`).
+pub fn render(text: &str) -> String {
+    let text = sanitize::text(text);
+    let mut lines = Vec::new();
+    let mut in_block = false;
+
+    for line in text.split('\n') {
+        let trimmed = line.trim();
+
+        if in_block {
+            if trimmed == FENCE {
+                in_block = false;
+            } else {
+                lines.push(format!("  {}", line.cyan()));
+            }
+
+            continue;
+        }
+
+        if let Some(code) = trimmed
+            .strip_prefix(FENCE)
+            .and_then(|rest| rest.strip_suffix(FENCE))
+            .filter(|code| !code.is_empty())
+        {
+            lines.push(code.cyan().to_string());
+        } else if trimmed.starts_with(FENCE) {
+            // o resto da linha de abertura é só o nome da linguagem
+            in_block = true;
+        } else if let Some(quote) = line.strip_prefix('>') {
+            lines.push(format!("{} {}", "│".dimmed(), inline(quote.trim_start())));
+        } else {
+            lines.push(inline(line));
+        }
+    }
+
+    sanitize::indent(&lines.join("\n"))
+}
+
+/// Estilos de uma linha só.
+fn inline(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut prev = None;
+    let mut i = 0;
+
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        let at_word_start = prev.is_none_or(|p: char| !p.is_alphanumeric());
+
+        if c == '`'
+            && let Some(end) = rest[1..].find('`')
+            && end > 0
+        {
+            let code = &rest[1..1 + end];
+
+            out.push_str(&code.cyan().to_string());
+            i += end + 2;
+            prev = Some('`');
+
+            continue;
+        }
+
+        if (c == '*' || c == '_')
+            && at_word_start
+            && let Some(end) = closing(rest, c)
+        {
+            let inner = inline(&rest[1..end]);
+            let styled = if c == '*' {
+                inner.bold().to_string()
+            } else {
+                inner.italic().to_string()
+            };
+
+            out.push_str(&styled);
+            i += end + 1;
+            prev = Some(c);
+
+            continue;
+        }
+
+        if c == '['
+            && let Some((label, url, len)) = link(rest)
+        {
+            out.push_str(&format!(
+                "{} {}",
+                label.underline(),
+                format!("({url})").dimmed()
+            ));
+            i += len;
+            prev = Some(')');
+
+            continue;
+        }
+
+        if at_word_start && (rest.starts_with("https://") || rest.starts_with("http://")) {
+            let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let url = &rest[..len];
+
+            out.push_str(&url.underline().to_string());
+            i += len;
+            prev = url.chars().next_back();
+
+            continue;
+        }
+
+        out.push(c);
+        i += c.len_utf8();
+        prev = Some(c);
+    }
+
+    out
+}
+
+/// Posição do delimitador que fecha o que abre em `text[0]`, se houver.
+///
+/// O conteúdo não pode começar nem terminar com espaço, e o fechamento não
+/// pode estar no meio de uma palavra (para `snake_case` não virar itálico).
+fn closing(text: &str, delim: char) -> Option<usize> {
+    let inner = &text[1..];
+
+    if inner.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    inner
+        .match_indices(delim)
+        .map(|(pos, _)| pos + 1)
+        .find(|&end| {
+            let before = text[..end].chars().next_back();
+            let after = text[end + 1..].chars().next();
+
+            end > 1
+                && before.is_some_and(|c| !c.is_whitespace())
+                && after.is_none_or(|c| !c.is_alphanumeric())
+        })
+}
+
+/// `[texto](url)` no começo de `text`: o texto, a url e o tamanho consumido.
+fn link(text: &str) -> Option<(&str, &str, usize)> {
+    let close = text.find(']')?;
+    let label = &text[1..close];
+    let rest = text[close + 1..].strip_prefix('(')?;
+    let end = rest.find(')')?;
+    let url = &rest[..end];
+
+    if label.is_empty() || !(url.starts_with("https://") || url.starts_with("http://")) {
+        return None;
+    }
+
+    Some((label, url, close + 1 + 1 + end + 1))
+}
+
+// endregion:    --- render
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// O texto que aparece, sem as cores.
+    fn plain(text: &str) -> String {
+        sanitize::strip_ansi(&render(text))
+    }
+
+    fn is_styled(text: &str) -> bool {
+        render(text).contains('\x1b')
+    }
+
+    #[test]
+    fn unclosed_markers_stay_literal() {
+        assert_eq!(plain("*negrito"), "*negrito");
+        assert_eq!(plain("_itálico"), "_itálico");
+        assert_eq!(plain("`código"), "`código");
+        assert_eq!(plain("[texto](https://x"), "[texto](https://x");
+        assert!(!is_styled("snake_case_name"));
+        assert!(!is_styled("* solto *"));
+    }
+
+    #[test]
+    fn styles_are_applied() {
+        assert_eq!(plain("*negrito* e _itálico_"), "negrito e itálico");
+        assert_eq!(plain("*um _dentro_ do outro*"), "um dentro do outro");
+        assert!(is_styled("*negrito*"));
+    }
+
+    #[test]
+    fn code_is_not_styled_inside() {
+        assert_eq!(plain("`*não* _é_`"), "*não* _é_");
+        assert_eq!(plain("```*x*```"), "*x*");
+        assert_eq!(plain("```rust\n*x*\n```\ndepois"), "  *x*\n  depois");
+    }
+
+    #[test]
+    fn only_web_links_are_links() {
+        assert_eq!(plain("[site](https://x.org)"), "site (https://x.org)");
+        assert_eq!(
+            plain("[site](javascript:alert(1))"),
+            "[site](javascript:alert(1))"
+        );
+        assert_eq!(
+            plain("[site](file:///etc/passwd)"),
+            "[site](file:///etc/passwd)"
+        );
+        assert!(!is_styled("ftp://x.org"));
+    }
+
+    #[test]
+    fn continuation_lines_are_indented() {
+        assert_eq!(plain("oi\nbob: tchau"), "oi\n  bob: tchau");
+        assert_eq!(plain("> citação\nbob: tchau"), "│ citação\n  bob: tchau");
+        assert_eq!(plain("oi\r\n\x1b[1Gbob: tchau"), "oi\n  bob: tchau");
+    }
+}
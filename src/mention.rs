@@ -0,0 +1,75 @@
+use std::collections::{BTreeSet, HashMap};
+
+use iroh::NodeId;
+use owo_colors::OwoColorize;
+
+/// Menor prefixo de id aceito em `@shortid`, para não casar por acaso.
+const MIN_SHORT_ID: usize = 4;
+
+/// Os `@tokens` do texto, com a posição de cada um (incluindo o `@`).
+fn tokens(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.char_indices().filter_map(move |(start, c)| {
+        let preceded_by_space = text[..start]
+            .chars()
+            .next_back()
+            .is_none_or(char::is_whitespace);
+
+        if c != '@' || !preceded_by_space {
+            return None;
+        }
+
+        let rest = &text[start + 1..];
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.'))
+            .unwrap_or(rest.len());
+        let token = rest[..len].trim_end_matches('.');
+
+        (!token.is_empty()).then_some((start, token))
+    })
+}
+
+/// Quem um `@token` pode ser: um prefixo de id ou um nome conhecido.
+pub fn resolve(token: &str, names: &HashMap<NodeId, String>) -> BTreeSet<NodeId> {
+    let by_id = names
+        .keys()
+        .filter(|actor| token.len() >= MIN_SHORT_ID && actor_id(actor).starts_with(token));
+    let by_name = names
+        .iter()
+        .filter(|(_, name)| !name.trim().is_empty() && name.trim().eq_ignore_ascii_case(token))
+        .map(|(actor, _)| actor);
+
+    by_id.chain(by_name).copied().collect()
+}
+
+/// Todos os atores mencionados no texto.
+pub fn mentioned(text: &str, names: &HashMap<NodeId, String>) -> BTreeSet<NodeId> {
+    tokens(text)
+        .flat_map(|(_, token)| resolve(token, names))
+        .collect()
+}
+
+/// Destaca as menções a `me` no texto.
+pub fn highlight(text: &str, me: NodeId, names: &HashMap<NodeId, String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for (start, token) in tokens(text) {
+        if !resolve(token, names).contains(&me) {
+            continue;
+        }
+
+        let end = start + 1 + token.len();
+        let mention = &text[start..end];
+
+        out.push_str(&text[last..start]);
+        out.push_str(&mention.black().on_yellow().to_string());
+        last = end;
+    }
+
+    out.push_str(&text[last..]);
+    out
+}
+
+fn actor_id(actor: &NodeId) -> String {
+    base58::encode(actor).into_string()
+}
@@ -0,0 +1,323 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use anyhow::Result;
+use ed25519_dalek::SigningKey;
+use iroh::NodeId;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::chat_event::{
+    ChatEvent, ChatEventBuilder, Initial, MAX_NAME_BYTES, MAX_TEXT_BYTES, RoomContext,
+    SignedChatEvent,
+};
+use crate::command::Command;
+use crate::group_key::Outbox;
+use crate::jsonl::JsonEvent;
+
+// region:       --- Plugin
+
+/// Um participante automático. Recebe os eventos verificados do tópico e
+/// pode emitir eventos próprios, assinados com a chave do bot.
+pub trait Plugin: Send + 'static {
+    /// Nome do bot, usado também para derivar a chave dele.
+    fn name(&self) -> &str;
+
+    /// Chamado uma vez antes do primeiro evento. Bots que emitem por conta
+    /// própria (lembretes, por exemplo) guardam um clone do contexto aqui.
+    fn start(&mut self, ctx: &PluginContext) -> Result<()> {
+        Ok(())
+    }
+
+    /// Chamado para cada evento verificado que não foi emitido pelo próprio bot.
+    fn on_event(&mut self, event: &ChatEvent, ctx: &PluginContext) -> Result<()>;
+}
+
+/// O que um bot precisa para falar no tópico.
+#[derive(Clone)]
+pub struct PluginContext {
+    key: SigningKey,
+    topic: String,
+    room: RoomContext,
+    outbox: mpsc::UnboundedSender<SignedChatEvent>,
+}
+
+impl PluginContext {
+    pub fn key(&self) -> &SigningKey {
+        &self.key
+    }
+
+    pub fn actor(&self) -> NodeId {
+        NodeId::from(self.key.verifying_key())
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// `ChatEvent::builder` para a sala do bot.
+    pub fn builder(&self) -> ChatEventBuilder<Initial, Initial> {
+        ChatEvent::builder(self.room)
+    }
+
+    /// Publica um evento já assinado, normalmente vindo de `builder()`.
+    pub fn emit(&self, event: SignedChatEvent) {
+        let _ = self.outbox.send(event);
+    }
+}
+
+// endregion:    --- Plugin
+
+// region:       --- PluginHost
+
+/// Roda os bots: despacha os eventos do barramento e publica o que eles emitem.
+pub struct PluginHost {
+    plugins: Vec<(Box<dyn Plugin>, PluginContext)>,
+    outbox: mpsc::UnboundedReceiver<SignedChatEvent>,
+}
+
+impl PluginHost {
+    /// `key_for` devolve a chave de cada bot a partir do nome dele.
+    pub fn new(
+        plugins: Vec<Box<dyn Plugin>>,
+        topic: &str,
+        room: RoomContext,
+        key_for: impl Fn(&str) -> Result<SigningKey>,
+    ) -> Result<Self> {
+        let (tx, outbox) = mpsc::unbounded_channel();
+        let plugins = plugins
+            .into_iter()
+            .map(|plugin| {
+                let ctx = PluginContext {
+                    key: key_for(plugin.name())?,
+                    topic: topic.to_string(),
+                    room,
+                    outbox: tx.clone(),
+                };
+
+                Ok((plugin, ctx))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { plugins, outbox })
+    }
+
+    pub fn spawn(self, sender: Outbox, bus: broadcast::Sender<ChatEvent>) {
+        let Self {
+            mut plugins,
+            mut outbox,
+        } = self;
+        let mut events = bus.subscribe();
+
+        tokio::spawn(async move {
+            for (plugin, ctx) in &mut plugins {
+                if let Err(e) = plugin.start(ctx) {
+                    eprintln!("[plugin {}] {e}", plugin.name());
+                }
+            }
+
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                for (plugin, ctx) in &mut plugins {
+                    if event.actor() == ctx.actor() {
+                        continue;
+                    }
+                    if let Err(e) = plugin.on_event(&event, ctx) {
+                        eprintln!("[plugin {}] {e}", plugin.name());
+                    }
+                }
+            }
+        });
+
+        tokio::spawn(async move {
+            while let Some(signed) = outbox.recv().await {
+                match sender.broadcast(&signed).await {
+                    Ok(true) => {}
+                    // sem a chave da sala ainda: não saiu, então não ecoa
+                    Ok(false) => continue,
+                    Err(_) => break,
+                }
+                if let Ok(event) = signed.verify_into(&sender.room()) {
+                    let _ = bus.send(event);
+                }
+            }
+        });
+    }
+}
+
+// endregion:    --- PluginHost
+
+// region:       --- ExternalPlugin
+
+/// Quantas linhas esperam o bot ler o stdin; além disso, os eventos são
+/// descartados até ele voltar a ler.
+const MAX_QUEUED_LINES: usize = 256;
+
+/// Um executável externo que fala o protocolo do `--format jsonl`: recebe os
+/// eventos no stdin e escreve comandos no stdout.
+pub struct ExternalPlugin {
+    name: String,
+    path: PathBuf,
+    stdin: Option<mpsc::Sender<String>>,
+    /// O stdin encheu e ainda não houve espaço de novo.
+    lagging: bool,
+}
+
+impl ExternalPlugin {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+
+        Self {
+            name,
+            path,
+            stdin: None,
+            lagging: false,
+        }
+    }
+}
+
+impl Plugin for ExternalPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn start(&mut self, ctx: &PluginContext) -> Result<()> {
+        let mut child = tokio::process::Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let mut child_stdin = child.stdin.take().unwrap();
+        let child_stdout = child.stdout.take().unwrap();
+        let (tx, mut rx) = mpsc::channel::<String>(MAX_QUEUED_LINES);
+
+        let ready = JsonEvent::ready(ctx.actor(), ctx.topic()).to_line();
+        let _ = tx.try_send(ready);
+
+        tokio::spawn(async move {
+            while let Some(mut line) = rx.recv().await {
+                line.push('\n');
+                if child_stdin.write_all(line.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let ctx = ctx.clone();
+        let plugin_name = self.name.clone();
+        let replies = tx.clone();
+        // recusas voltam para o bot; se ele não lê, também não precisa delas
+        let report = move |ctx: &PluginContext, error: &str| {
+            let _ = replies.try_send(JsonEvent::error(ctx.actor(), ctx.topic(), error).to_line());
+        };
+
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(child_stdout).lines();
+            let mut name = plugin_name.clone();
+
+            while let Ok(Some(line)) = lines.next_line().await {
+                let command = match Command::parse_json(&line) {
+                    Ok(Some(command)) => command,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        eprintln!("[plugin {plugin_name}] invalid command: {e}");
+                        report(&ctx, &format!("invalid command: {e}"));
+
+                        continue;
+                    }
+                };
+
+                // os pares descartariam sem avisar, como no terminal
+                let too_long = match &command {
+                    Command::Send(message) => message.len() > MAX_TEXT_BYTES,
+                    Command::Name(name) => name.len() > MAX_NAME_BYTES,
+                    _ => false,
+                };
+
+                if too_long {
+                    report(&ctx, "too long");
+
+                    continue;
+                }
+
+                let event = match command {
+                    Command::Send(message) => {
+                        ctx.builder()
+                            .new_message(&name, message)
+                            .sign(ctx.key())
+                            .await
+                    }
+                    Command::Name(new_name) => {
+                        name = new_name;
+
+                        continue;
+                    }
+                    Command::Join => ctx.builder().node_joined().sign(ctx.key()).await,
+                    Command::Leave => ctx.builder().node_left().sign(ctx.key()).await,
+                    // bots não têm blob store nem histórico próprios
+                    Command::SendFile(_)
+                    | Command::Get(_)
+                    | Command::Image(_)
+                    | Command::Edit(..)
+                    | Command::Delete(_)
+                    | Command::Reply(..)
+                    | Command::Thread(_)
+                    | Command::Seen(_)
+                    | Command::Nick(..)
+                    | Command::Verify(..)
+                    | Command::Block(..)
+                    | Command::Mute(..)
+                    | Command::AddMember(_)
+                    | Command::RemoveMember(_)
+                    | Command::Promote(_)
+                    | Command::Members
+                    | Command::Direct(..)
+                    | Command::Stats
+                    | Command::React(..) => continue,
+                    Command::Exit => break,
+                };
+
+                ctx.emit(event);
+            }
+
+            let _ = child.kill().await;
+        });
+
+        self.stdin = Some(tx);
+
+        Ok(())
+    }
+
+    fn on_event(&mut self, event: &ChatEvent, ctx: &PluginContext) -> Result<()> {
+        let Some(stdin) = &self.stdin else {
+            return Ok(());
+        };
+
+        match stdin.try_send(JsonEvent::new(event, ctx.topic()).to_line()) {
+            Ok(()) => self.lagging = false,
+            Err(TrySendError::Full(_)) if !self.lagging => {
+                self.lagging = true;
+
+                eprintln!(
+                    "[plugin {}] not reading its stdin; dropping events",
+                    self.name
+                );
+            }
+            Err(_) => {}
+        }
+
+        Ok(())
+    }
+}
+
+// endregion:    --- ExternalPlugin
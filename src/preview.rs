@@ -0,0 +1,220 @@
+use std::fmt::Write as _;
+use std::io::Cursor;
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use clap::ValueEnum;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{ImageFormat, ImageReader, Limits, RgbImage};
+use owo_colors::OwoColorize;
+
+/// Teto do thumbnail codificado, para caber numa mensagem de gossip (4 KiB).
+pub const MAX_THUMBNAIL_BYTES: usize = 3 * 1024;
+
+/// Lados tentados, do maior para o menor, até o JPEG caber no teto.
+const THUMBNAIL_SIDES: [u32; 4] = [96, 64, 48, 32];
+
+/// Largura máxima, em colunas, do fallback com meios-blocos.
+const BLOCK_COLUMNS: u32 = 48;
+
+/// Como desenhar imagens recebidas no terminal.
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ImageMode {
+    /// Only show a text placeholder.
+    #[default]
+    Off,
+    /// Pick a protocol from the environment.
+    Auto,
+    /// Kitty graphics protocol.
+    Kitty,
+    /// Sixel graphics.
+    Sixel,
+    /// Unicode half blocks with truecolor.
+    Blocks,
+}
+
+impl ImageMode {
+    /// Resolve o `Auto` olhando as variáveis do terminal.
+    pub fn resolve(self) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+
+        let term = std::env::var("TERM").unwrap_or_default();
+        let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+
+        if std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || term.contains("kitty")
+            || term_program == "WezTerm"
+            || term_program == "ghostty"
+        {
+            Self::Kitty
+        } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+            Self::Sixel
+        } else {
+            Self::Blocks
+        }
+    }
+}
+
+// region:       --- thumbnail
+
+/// Decodifica uma imagem local e reencoda como um JPEG pequeno.
+///
+/// Retorna largura, altura e os bytes do thumbnail.
+pub fn make_thumbnail(path: &Path) -> Result<(u32, u32, Vec<u8>)> {
+    let image = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+
+    for side in THUMBNAIL_SIDES {
+        let thumbnail = image.resize(side, side, FilterType::Triangle).to_rgb8();
+        let mut bytes = Vec::new();
+
+        JpegEncoder::new_with_quality(&mut bytes, 70).encode_image(&thumbnail)?;
+
+        if bytes.len() <= MAX_THUMBNAIL_BYTES {
+            return Ok((thumbnail.width(), thumbnail.height(), bytes));
+        }
+    }
+
+    bail!("image too detailed for a thumbnail")
+}
+
+/// Decodifica um thumbnail recebido, com limites para não confiar no par.
+fn decode_thumbnail(data: &[u8]) -> Result<RgbImage> {
+    if data.len() > MAX_THUMBNAIL_BYTES {
+        bail!("thumbnail too large");
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(THUMBNAIL_SIDES[0]);
+    limits.max_image_height = Some(THUMBNAIL_SIDES[0]);
+
+    let mut reader = ImageReader::with_format(Cursor::new(data), ImageFormat::Jpeg);
+    reader.limits(limits);
+
+    Ok(reader.decode()?.to_rgb8())
+}
+
+// endregion:    --- thumbnail
+
+// region:       --- render
+
+/// Desenha um thumbnail recebido no modo pedido. `Off` não desenha nada.
+pub fn render(data: &[u8], mode: ImageMode) -> Result<String> {
+    let image = decode_thumbnail(data)?;
+
+    let rendered = match mode.resolve() {
+        ImageMode::Off | ImageMode::Auto => String::new(),
+        ImageMode::Kitty => render_kitty(&image)?,
+        ImageMode::Sixel => render_sixel(&image),
+        ImageMode::Blocks => render_blocks(&image),
+    };
+
+    Ok(rendered)
+}
+
+fn render_kitty(image: &RgbImage) -> Result<String> {
+    let mut png = Vec::new();
+
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+
+    let encoded = BASE64.encode(png);
+    let chunks = encoded.as_bytes().chunks(4096).collect::<Vec<_>>();
+    let mut out = String::new();
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).unwrap();
+
+        if i == 0 {
+            write!(out, "\x1b_Gf=100,a=T,m={more};{chunk}\x1b\\").unwrap();
+        } else {
+            write!(out, "\x1b_Gm={more};{chunk}\x1b\\").unwrap();
+        }
+    }
+
+    Ok(out)
+}
+
+/// Sixel com a paleta fixa de 6x6x6 cores.
+fn render_sixel(image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+    let level = |c: u8| (c as u16 * 5 / 255) as usize;
+    let index = |x: u32, y: u32| {
+        let [r, g, b] = image.get_pixel(x, y).0;
+        level(r) * 36 + level(g) * 6 + level(b)
+    };
+    let mut out = format!("\x1bPq\"1;1;{width};{height}");
+
+    for i in 0..216 {
+        let (r, g, b) = (i / 36, i / 6 % 6, i % 6);
+        write!(out, "#{i};2;{};{};{}", r * 20, g * 20, b * 20).unwrap();
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = band..(band + 6).min(height);
+        let mut used = [false; 216];
+
+        for y in rows.clone() {
+            for x in 0..width {
+                used[index(x, y)] = true;
+            }
+        }
+
+        for color in (0..216).filter(|&c| used[c]) {
+            write!(out, "#{color}").unwrap();
+
+            for x in 0..width {
+                let bits = rows
+                    .clone()
+                    .filter(|&y| index(x, y) == color)
+                    .fold(0u8, |acc, y| acc | 1 << (y - band));
+
+                out.push((63 + bits) as char);
+            }
+
+            out.push('$');
+        }
+
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\");
+    out
+}
+
+/// Dois pixels por célula: o de cima na cor do texto, o de baixo no fundo.
+fn render_blocks(image: &RgbImage) -> String {
+    let image = if image.width() > BLOCK_COLUMNS {
+        let height = image.height() * BLOCK_COLUMNS / image.width();
+        image::imageops::resize(image, BLOCK_COLUMNS, height.max(1), FilterType::Triangle)
+    } else {
+        image.clone()
+    };
+    let (width, height) = image.dimensions();
+    let mut out = String::new();
+
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let [r, g, b] = image.get_pixel(x, y).0;
+            let [br, bg, bb] = if y + 1 < height {
+                image.get_pixel(x, y + 1).0
+            } else {
+                [0, 0, 0]
+            };
+
+            write!(out, "{}", "▀".truecolor(r, g, b).on_truecolor(br, bg, bb)).unwrap();
+        }
+
+        if y + 2 < height {
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+// endregion:    --- render
@@ -0,0 +1,422 @@
+use std::collections::BTreeMap;
+
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit, Nonce};
+use curve25519_dalek::MontgomeryPoint;
+use ed25519_dalek::SigningKey;
+use iroh::NodeId;
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
+
+/// Contexto do `blake3::derive_key` do segredo inicial de uma sessão.
+const SESSION_CONTEXT: &str = "chat-p2p 2025-10 direct session";
+
+/// Contexto do `blake3::derive_key` da cadeia raiz.
+const ROOT_CONTEXT: &str = "chat-p2p 2025-10 ratchet root";
+
+/// Quantas mensagens de uma cadeia podem ser puladas de uma vez.
+const MAX_SKIP: u32 = 256;
+
+/// Quantas chaves de mensagens puladas ficam guardadas, no total.
+const MAX_SKIPPED: usize = 1024;
+
+// region:       --- structs
+
+/// Uma sessão double ratchet com um par, no papel decidido pelos ids.
+///
+/// O segredo inicial sai do Diffie-Hellman das chaves X25519 derivadas dos
+/// dois ids; quem tem o id menor começa a cadeia. A cada resposta, o lado que
+/// recebe troca o próprio par de chaves efêmeras, então uma chave vazada hoje
+/// não abre o que já passou (sigilo futuro) e deixa de abrir o que vem depois
+/// da próxima troca (recuperação depois de comprometimento).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    root: [u8; 32],
+    /// Nossa chave efêmera atual, em bytes que `mul_clamped` aceita.
+    secret: [u8; 32],
+    remote: Option<[u8; 32]>,
+    send: Option<Chain>,
+    recv: Option<Chain>,
+    /// Quantas mensagens a cadeia de envio anterior teve.
+    previous: u32,
+    skipped: BTreeMap<([u8; 32], u32), [u8; 32]>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Chain {
+    key: [u8; 32],
+    n: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    /// A chave efêmera de quem mandou.
+    pub dh: [u8; 32],
+    pub previous: u32,
+    pub n: u32,
+}
+
+/// Uma mensagem cifrada da sessão. O cabeçalho vai aberto, mas autenticado.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub header: Header,
+    pub ciphertext: Vec<u8>,
+}
+
+#[derive(Debug, ThisError)]
+#[error("{self:?}")]
+pub enum RatchetError {
+    /// Ainda não recebemos nada do par, que é quem começa a cadeia.
+    NoSendChain,
+    TooManySkipped,
+    Decrypt,
+
+    // externals
+    Postcard(#[from] postcard::Error),
+}
+
+// endregion:    --- structs
+
+// region:       --- Session impl
+
+impl Session {
+    /// Se somos nós que começamos a sessão com `peer`: quem tem o id menor.
+    pub fn starts(me: &SigningKey, peer: &NodeId) -> bool {
+        NodeId::from(me.verifying_key()) < *peer
+    }
+
+    /// A sessão de quem começa: já pode mandar, para a chave fixa do par.
+    pub fn initiator(me: &SigningKey, peer: &NodeId) -> Self {
+        let remote = peer.public().to_montgomery().to_bytes();
+        let secret = rand::random();
+        let (root, key) = kdf_root(&shared_secret(me, peer), &dh(&secret, &remote));
+
+        Self {
+            root,
+            secret,
+            remote: Some(remote),
+            send: Some(Chain { key, n: 0 }),
+            recv: None,
+            previous: 0,
+            skipped: BTreeMap::new(),
+        }
+    }
+
+    /// A sessão de quem responde: só manda depois da primeira mensagem.
+    pub fn responder(me: &SigningKey, peer: &NodeId) -> Self {
+        Self {
+            root: shared_secret(me, peer),
+            secret: me.to_scalar_bytes(),
+            remote: None,
+            send: None,
+            recv: None,
+            previous: 0,
+            skipped: BTreeMap::new(),
+        }
+    }
+
+    pub fn can_send(&self) -> bool {
+        self.send.is_some()
+    }
+
+    /// Se a sessão ainda não cifrou nem decifrou nada.
+    pub fn is_unused(&self) -> bool {
+        self.recv.is_none() && self.send.as_ref().is_none_or(|chain| chain.n == 0)
+    }
+
+    /// Cifra `plaintext`, autenticando também `ad` (os ids dos dois lados).
+    pub fn encrypt(&mut self, plaintext: &[u8], ad: &[u8]) -> Result<Message, RatchetError> {
+        let chain = self.send.as_mut().ok_or(RatchetError::NoSendChain)?;
+        let (key, message_key) = kdf_chain(&chain.key);
+        let header = Header {
+            dh: public(&self.secret),
+            previous: self.previous,
+            n: chain.n,
+        };
+
+        chain.key = key;
+        chain.n += 1;
+
+        let ciphertext = seal(&message_key, &header, plaintext, ad)?;
+
+        Ok(Message { header, ciphertext })
+    }
+
+    /// Decifra uma mensagem do par, andando a catraca se ela trouxer uma chave
+    /// efêmera nova. Se falhar, a sessão fica como estava.
+    pub fn decrypt(&mut self, message: &Message, ad: &[u8]) -> Result<Vec<u8>, RatchetError> {
+        let mut next = self.clone();
+        let plaintext = next.decrypt_in_place(message, ad)?;
+
+        *self = next;
+
+        Ok(plaintext)
+    }
+
+    fn decrypt_in_place(&mut self, message: &Message, ad: &[u8]) -> Result<Vec<u8>, RatchetError> {
+        let header = &message.header;
+
+        if let Some(key) = self.skipped.remove(&(header.dh, header.n)) {
+            return open(&key, header, &message.ciphertext, ad);
+        }
+
+        if self.remote != Some(header.dh) {
+            self.skip(header.previous)?;
+            self.step(header.dh);
+        }
+
+        self.skip(header.n)?;
+
+        let chain = self.recv.as_mut().ok_or(RatchetError::Decrypt)?;
+        let (key, message_key) = kdf_chain(&chain.key);
+
+        chain.key = key;
+        chain.n += 1;
+
+        open(&message_key, header, &message.ciphertext, ad)
+    }
+
+    /// Guarda as chaves das mensagens da cadeia atual até `until`, que podem
+    /// chegar fora de ordem.
+    fn skip(&mut self, until: u32) -> Result<(), RatchetError> {
+        let (Some(chain), Some(remote)) = (self.recv.as_mut(), self.remote) else {
+            return Ok(());
+        };
+
+        if until > chain.n.saturating_add(MAX_SKIP) {
+            return Err(RatchetError::TooManySkipped);
+        }
+
+        while chain.n < until {
+            let (key, message_key) = kdf_chain(&chain.key);
+
+            if self.skipped.len() == MAX_SKIPPED {
+                self.skipped.pop_first();
+            }
+
+            self.skipped.insert((remote, chain.n), message_key);
+            chain.key = key;
+            chain.n += 1;
+        }
+
+        Ok(())
+    }
+
+    /// O passo Diffie-Hellman: nova cadeia de recebimento com a chave do par
+    /// e nova chave efêmera nossa para a cadeia de envio.
+    fn step(&mut self, remote: [u8; 32]) {
+        self.previous = self.send.as_ref().map_or(0, |chain| chain.n);
+        self.remote = Some(remote);
+
+        let (root, key) = kdf_root(&self.root, &dh(&self.secret, &remote));
+
+        self.recv = Some(Chain { key, n: 0 });
+        self.secret = rand::random();
+
+        let (root, key) = kdf_root(&root, &dh(&self.secret, &remote));
+
+        self.root = root;
+        self.send = Some(Chain { key, n: 0 });
+    }
+}
+
+// endregion:    --- Session impl
+
+// region:       --- utils
+
+/// Os ids dos dois lados, o menor primeiro, para autenticar junto de cada
+/// mensagem.
+pub fn associated_data(me: &SigningKey, peer: &NodeId) -> Vec<u8> {
+    let me = NodeId::from(me.verifying_key());
+    let (first, second) = if me < *peer { (me, *peer) } else { (*peer, me) };
+
+    [first.as_bytes().as_slice(), second.as_bytes()].concat()
+}
+
+/// O segredo inicial: o Diffie-Hellman das chaves fixas, amarrado aos ids.
+fn shared_secret(me: &SigningKey, peer: &NodeId) -> [u8; 32] {
+    let remote = peer.public().to_montgomery().to_bytes();
+    let material = [
+        dh(&me.to_scalar_bytes(), &remote).as_slice(),
+        &associated_data(me, peer),
+    ]
+    .concat();
+
+    blake3::derive_key(SESSION_CONTEXT, &material)
+}
+
+fn dh(secret: &[u8; 32], public: &[u8; 32]) -> [u8; 32] {
+    MontgomeryPoint(*public).mul_clamped(*secret).to_bytes()
+}
+
+fn public(secret: &[u8; 32]) -> [u8; 32] {
+    MontgomeryPoint::mul_base_clamped(*secret).to_bytes()
+}
+
+fn kdf_root(root: &[u8; 32], dh: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let mut out = [0; 64];
+
+    blake3::Hasher::new_derive_key(ROOT_CONTEXT)
+        .update(root)
+        .update(dh)
+        .finalize_xof()
+        .fill(&mut out);
+
+    (out[..32].try_into().unwrap(), out[32..].try_into().unwrap())
+}
+
+/// A próxima chave da cadeia e a chave da mensagem atual.
+fn kdf_chain(key: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    (
+        *blake3::keyed_hash(key, &[1]).as_bytes(),
+        *blake3::keyed_hash(key, &[2]).as_bytes(),
+    )
+}
+
+/// Cada chave de mensagem só é usada uma vez, então o nonce pode ser fixo.
+fn seal(
+    key: &[u8; 32],
+    header: &Header,
+    plaintext: &[u8],
+    ad: &[u8],
+) -> Result<Vec<u8>, RatchetError> {
+    let aad = [ad, &postcard::to_allocvec(header)?].concat();
+
+    ChaCha20Poly1305::new(key.into())
+        .encrypt(
+            &Nonce::default(),
+            Payload {
+                msg: plaintext,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| RatchetError::Decrypt)
+}
+
+fn open(
+    key: &[u8; 32],
+    header: &Header,
+    ciphertext: &[u8],
+    ad: &[u8],
+) -> Result<Vec<u8>, RatchetError> {
+    let aad = [ad, &postcard::to_allocvec(header)?].concat();
+
+    ChaCha20Poly1305::new(key.into())
+        .decrypt(
+            &Nonce::default(),
+            Payload {
+                msg: ciphertext,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| RatchetError::Decrypt)
+}
+
+// endregion:    --- utils
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair() -> (SigningKey, SigningKey, Vec<u8>) {
+        let mut keys = [
+            SigningKey::from_bytes(&rand::random()),
+            SigningKey::from_bytes(&rand::random()),
+        ];
+
+        keys.sort_by_key(|key| NodeId::from(key.verifying_key()));
+
+        let [alice, bob] = keys;
+        let ad = associated_data(&alice, &NodeId::from(bob.verifying_key()));
+
+        (alice, bob, ad)
+    }
+
+    fn sessions(alice: &SigningKey, bob: &SigningKey) -> (Session, Session) {
+        let alice_id = NodeId::from(alice.verifying_key());
+        let bob_id = NodeId::from(bob.verifying_key());
+
+        assert!(Session::starts(alice, &bob_id));
+
+        (
+            Session::initiator(alice, &bob_id),
+            Session::responder(bob, &alice_id),
+        )
+    }
+
+    #[test]
+    fn both_sides_talk() {
+        let (alice_key, bob_key, ad) = pair();
+        let (mut alice, mut bob) = sessions(&alice_key, &bob_key);
+
+        assert!(!bob.can_send());
+
+        for text in [b"oi".as_slice(), b"tudo bem?"] {
+            let message = alice.encrypt(text, &ad).unwrap();
+
+            assert_eq!(bob.decrypt(&message, &ad).unwrap(), text);
+        }
+
+        let reply = bob.encrypt(b"tudo", &ad).unwrap();
+
+        assert_eq!(alice.decrypt(&reply, &ad).unwrap(), b"tudo");
+
+        let again = alice.encrypt(b"que bom", &ad).unwrap();
+
+        // cada resposta troca a chave efêmera
+        assert_ne!(again.header.dh, reply.header.dh);
+        assert_eq!(bob.decrypt(&again, &ad).unwrap(), b"que bom");
+    }
+
+    #[test]
+    fn out_of_order_and_replay() {
+        let (alice_key, bob_key, ad) = pair();
+        let (mut alice, mut bob) = sessions(&alice_key, &bob_key);
+        let first = alice.encrypt(b"1", &ad).unwrap();
+        let second = alice.encrypt(b"2", &ad).unwrap();
+
+        assert_eq!(bob.decrypt(&second, &ad).unwrap(), b"2");
+        assert_eq!(bob.decrypt(&first, &ad).unwrap(), b"1");
+        // a chave de cada mensagem some depois de usada
+        assert!(bob.decrypt(&first, &ad).is_err());
+    }
+
+    #[test]
+    fn tampering_leaves_the_session_intact() {
+        let (alice_key, bob_key, ad) = pair();
+        let (mut alice, mut bob) = sessions(&alice_key, &bob_key);
+        let message = alice.encrypt(b"oi", &ad).unwrap();
+        let mut tampered = message.clone();
+
+        tampered.header.dh = rand::random();
+
+        assert!(bob.decrypt(&tampered, &ad).is_err());
+        assert_eq!(bob.decrypt(&message, &ad).unwrap(), b"oi");
+    }
+
+    #[test]
+    fn old_keys_do_not_open_new_messages() {
+        let (alice_key, bob_key, ad) = pair();
+        let (mut alice, mut bob) = sessions(&alice_key, &bob_key);
+
+        bob.decrypt(&alice.encrypt(b"1", &ad).unwrap(), &ad)
+            .unwrap();
+
+        // quem copiou o estado do bob aqui perde o fio depois de uma volta
+        let mut stolen = bob.clone();
+
+        alice
+            .decrypt(&bob.encrypt(b"2", &ad).unwrap(), &ad)
+            .unwrap();
+        bob.decrypt(&alice.encrypt(b"3", &ad).unwrap(), &ad)
+            .unwrap();
+        alice
+            .decrypt(&bob.encrypt(b"4", &ad).unwrap(), &ad)
+            .unwrap();
+
+        let message = alice.encrypt(b"5", &ad).unwrap();
+
+        assert!(stolen.decrypt(&message, &ad).is_err());
+        assert_eq!(bob.decrypt(&message, &ad).unwrap(), b"5");
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use iroh::NodeId;
+
+/// Eventos por segundo que cada par pode mandar, em média.
+pub const DEFAULT_RATE: f64 = 5.0;
+
+/// Quantos eventos seguidos cabem antes do limite valer.
+pub const DEFAULT_BURST: f64 = 20.0;
+
+/// Por quanto tempo quem estoura o limite deixa de ser ouvido.
+pub const MUTE_FOR: Duration = Duration::from_secs(60);
+
+/// Depois de quanto tempo parado um par sai da tabela, e de quanto em quanto
+/// tempo a tabela é varrida.
+const IDLE_FOR: Duration = Duration::from_secs(10 * 60);
+
+// region:       --- structs
+
+/// Um balde de fichas por par e as contagens do `/stats`.
+///
+/// Cada evento gasta uma ficha, e o balde enche `rate` fichas por segundo até
+/// `burst`. Quem tenta gastar com o balde vazio perde o evento e fica
+/// `MUTE_FOR` sem ser ouvido. Quem fica `IDLE_FOR` parado, com o balde já
+/// cheio de novo, sai da tabela (e das contagens por par): qualquer um pode
+/// assinar com uma chave nova a cada evento.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    peers: HashMap<NodeId, Peer>,
+    stats: Stats,
+    swept: Instant,
+}
+
+/// Contagens de tudo que chegou do gossip.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub received: u64,
+    pub oversized: u64,
+    pub invalid: u64,
+    pub blocked: u64,
+    pub limited: u64,
+}
+
+/// Contagens de um par, e quanto falta para ele voltar a ser ouvido.
+#[derive(Debug, Clone, Copy)]
+pub struct PeerStats {
+    pub received: u64,
+    pub dropped: u64,
+    pub muted_for: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Peer {
+    tokens: f64,
+    last: Instant,
+    muted_until: Option<Instant>,
+    received: u64,
+    dropped: u64,
+}
+
+// endregion:    --- structs
+
+// region:       --- RateLimiter impl
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_RATE, DEFAULT_BURST)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst,
+            peers: HashMap::new(),
+            stats: Stats::default(),
+            swept: Instant::now(),
+        }
+    }
+
+    /// Gasta uma ficha do par. Retorna `false` se o evento deve ser descartado.
+    pub fn allow(&mut self, actor: NodeId) -> bool {
+        self.allow_at(actor, Instant::now())
+    }
+
+    fn allow_at(&mut self, actor: NodeId, now: Instant) -> bool {
+        if now - self.swept >= IDLE_FOR {
+            self.sweep(now);
+        }
+
+        let peer = self.peers.entry(actor).or_insert(Peer {
+            tokens: self.burst,
+            last: now,
+            muted_until: None,
+            received: 0,
+            dropped: 0,
+        });
+
+        peer.tokens = (peer.tokens + self.rate * (now - peer.last).as_secs_f64()).min(self.burst);
+        peer.last = now;
+
+        let muted = peer.muted_until.is_some_and(|until| now < until);
+
+        if !muted && peer.tokens < 1.0 {
+            peer.muted_until = Some(now + MUTE_FOR);
+        }
+
+        if muted || peer.tokens < 1.0 {
+            peer.dropped += 1;
+            self.stats.limited += 1;
+
+            return false;
+        }
+
+        peer.tokens -= 1.0;
+        peer.received += 1;
+        self.stats.received += 1;
+
+        true
+    }
+
+    /// Esquece quem está parado há `IDLE_FOR` e voltaria com o balde cheio e
+    /// sem castigo, então não perde nada sendo recriado.
+    fn sweep(&mut self, now: Instant) {
+        let (rate, burst) = (self.rate, self.burst);
+
+        self.peers.retain(|_, peer| {
+            let idle = now - peer.last;
+            let refilled = peer.tokens + rate * idle.as_secs_f64() >= burst;
+            let muted = peer.muted_until.is_some_and(|until| now < until);
+
+            idle < IDLE_FOR || !refilled || muted
+        });
+        self.swept = now;
+    }
+
+    /// Para o recebimento contar o que descartou antes de chegar aqui.
+    pub fn stats_mut(&mut self) -> &mut Stats {
+        &mut self.stats
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// As contagens de cada par, de quem mais mandou para quem menos mandou.
+    pub fn peers(&self) -> Vec<(NodeId, PeerStats)> {
+        self.peers_at(Instant::now())
+    }
+
+    fn peers_at(&self, now: Instant) -> Vec<(NodeId, PeerStats)> {
+        let mut peers = self
+            .peers
+            .iter()
+            .map(|(actor, peer)| {
+                let stats = PeerStats {
+                    received: peer.received,
+                    dropped: peer.dropped,
+                    muted_for: peer
+                        .muted_until
+                        .filter(|until| now < *until)
+                        .map(|until| until - now),
+                };
+
+                (*actor, stats)
+            })
+            .collect::<Vec<_>>();
+
+        peers.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.received + stats.dropped));
+
+        peers
+    }
+}
+
+// endregion:    --- RateLimiter impl
+
+// region:       --- utils
+
+/// Lê o `--rate`: um número finito maior que zero, ou o balde nunca enche.
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("{s} is not a number of events per second above 0")),
+    }
+}
+
+/// Lê o `--burst`: pelo menos uma ficha, ou nenhum evento passa.
+pub fn parse_burst(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(burst) if burst.is_finite() && burst >= 1.0 => Ok(burst),
+        _ => Err(format!("{s} is not a number of events of at least 1")),
+    }
+}
+
+// endregion:    --- utils
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use super::*;
+
+    fn peer() -> NodeId {
+        SecretKey::generate(rand::rngs::OsRng).public()
+    }
+
+    fn stats(limiter: &RateLimiter, actor: NodeId, now: Instant) -> PeerStats {
+        limiter
+            .peers_at(now)
+            .into_iter()
+            .find(|(peer, _)| *peer == actor)
+            .map(|(_, stats)| stats)
+            .unwrap()
+    }
+
+    #[test]
+    fn burst_then_rate() {
+        let mut limiter = RateLimiter::new(2.0, 3.0);
+        let actor = peer();
+        let now = Instant::now();
+
+        assert!((0..3).all(|_| limiter.allow_at(actor, now)));
+        assert!(!limiter.allow_at(actor, now));
+
+        let stats = stats(&limiter, actor, now);
+
+        assert_eq!((stats.received, stats.dropped), (3, 1));
+        // outro par tem o próprio balde
+        assert!(limiter.allow_at(peer(), now));
+    }
+
+    #[test]
+    fn overflow_mutes_for_a_minute() {
+        let mut limiter = RateLimiter::new(2.0, 1.0);
+        let actor = peer();
+        let now = Instant::now();
+
+        assert!(limiter.allow_at(actor, now));
+        assert!(!limiter.allow_at(actor, now));
+
+        // o balde já encheu, mas o castigo ainda vale
+        let later = now + Duration::from_secs(30);
+
+        assert!(!limiter.allow_at(actor, later));
+        assert!(stats(&limiter, actor, later).muted_for.is_some());
+
+        let after = now + MUTE_FOR + Duration::from_secs(1);
+
+        assert!(limiter.allow_at(actor, after));
+        assert!(stats(&limiter, actor, after).muted_for.is_none());
+    }
+
+    #[test]
+    fn idle_peers_are_forgotten() {
+        let mut limiter = RateLimiter::new(1.0, 2.0);
+        let (idle, muted, busy) = (peer(), peer(), peer());
+        let now = Instant::now();
+
+        limiter.allow_at(idle, now);
+
+        // `muted` estoura agora e é castigado até bem depois da varredura
+        let late = now + IDLE_FOR;
+
+        limiter.allow_at(muted, late - MUTE_FOR / 2);
+        limiter.allow_at(muted, late - MUTE_FOR / 2);
+        limiter.allow_at(muted, late - MUTE_FOR / 2);
+        limiter.allow_at(busy, late);
+
+        let peers = limiter
+            .peers_at(late)
+            .into_iter()
+            .map(|(actor, _)| actor)
+            .collect::<Vec<_>>();
+
+        assert!(!peers.contains(&idle));
+        assert!(peers.contains(&muted));
+        assert!(peers.contains(&busy));
+    }
+
+    #[test]
+    fn flags_must_make_sense() {
+        assert_eq!(parse_rate("0.5"), Ok(0.5));
+        assert_eq!(parse_burst("1"), Ok(1.0));
+
+        for bad in ["0", "-1", "NaN", "inf", "x"] {
+            assert!(parse_rate(bad).is_err(), "{bad}");
+        }
+
+        for bad in ["0.5", "-1", "NaN", "inf"] {
+            assert!(parse_burst(bad).is_err(), "{bad}");
+        }
+    }
+}
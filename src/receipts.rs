@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use ed25519_dalek::SigningKey;
+use iroh::NodeId;
+use tokio::sync::broadcast;
+
+use crate::chat_event::{ChatEvent, MessageId, ReceiptKind};
+use crate::group_key::Outbox;
+
+/// Espera antes de confirmar o recebimento, para juntar rajadas numa só confirmação.
+const DELIVERY_DELAY: Duration = Duration::from_secs(1);
+
+/// Confirma o recebimento das mensagens dos outros, por marca d'água.
+///
+/// Cada confirmação cobre tudo até a última mensagem vista, então basta
+/// mandar uma por rajada.
+pub fn spawn_delivered(
+    mut events: broadcast::Receiver<ChatEvent>,
+    sender: Outbox,
+    key: SigningKey,
+    me: NodeId,
+) {
+    tokio::spawn(async move {
+        loop {
+            let mut latest = loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        if let Some(id) = from_others(&event, me) {
+                            break id;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            };
+
+            tokio::time::sleep(DELIVERY_DELAY).await;
+
+            loop {
+                match events.try_recv() {
+                    Ok(event) => {
+                        if let Some(id) = from_others(&event, me) {
+                            latest = id;
+                        }
+                    }
+                    Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+                    Err(_) => break,
+                }
+            }
+
+            let event = ChatEvent::builder(sender.room())
+                .receipt(ReceiptKind::Delivered, latest)
+                .sign(&key)
+                .await;
+
+            if sender.broadcast(&event).await.is_err() {
+                return;
+            }
+        }
+    });
+}
+
+fn from_others(event: &ChatEvent, me: NodeId) -> Option<MessageId> {
+    match event {
+        ChatEvent::NewMessage { actor, id, .. } if *actor != me => Some(*id),
+        _ => None,
+    }
+}
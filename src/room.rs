@@ -0,0 +1,790 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use ed25519_dalek::SigningKey;
+use iroh::NodeId;
+
+use crate::chat_event::{ChatEvent, MAX_PARENTS, MessageId, RoomContext, SignedChatEvent};
+use crate::config::rooms_path;
+use crate::group_key::{self, GroupKey};
+
+/// Quantas operações com pais desconhecidos esperam na memória.
+const MAX_PENDING: usize = 256;
+
+/// Quantas dessas podem ser de quem assinou; as mais velhas dele saem antes.
+const MAX_PENDING_PER_AUTHOR: usize = 16;
+
+// region:       --- structs
+
+/// Sala com dono, cuja lista de membros sai de um log de operações assinadas.
+///
+/// Cada operação cita as pontas do log que quem assinou já tinha visto, e o
+/// `seq` dela é um a mais que o maior dos pais; ninguém escolhe o próprio
+/// `seq`. Todos os nós aplicam as operações na mesma ordem, `(seq, id)`, que
+/// respeita essas dependências, então quem tem o mesmo log chega nos mesmos
+/// membros. O log fica gravado em `~/.chat-p2p/rooms/<tópico>`.
+///
+/// A permissão de cada operação é conferida contra o estado do passado dela,
+/// não contra o que veio antes na ordem. Um admin removido pelo dono perde
+/// também o que assinou sem ter visto a remoção, para não poder assinar "no
+/// passado" depois de sair.
+///
+/// As chaves da sala também passam pelo log: cada `RoomKey` abre uma época,
+/// com a chave selada só para quem era membro. Quem sai não recebe a próxima.
+#[derive(Debug)]
+pub struct Room {
+    owner: NodeId,
+    me: SigningKey,
+    path: PathBuf,
+    ops: BTreeMap<Key, Op>,
+    seqs: HashMap<MessageId, u64>,
+    valid: BTreeSet<MessageId>,
+    pending: Vec<(Vec<u8>, ChatEvent)>,
+    members: BTreeSet<NodeId>,
+    admins: BTreeSet<NodeId>,
+    epoch: Option<u64>,
+    keys: BTreeMap<u64, GroupKey>,
+}
+
+#[derive(Debug)]
+struct Op {
+    event: ChatEvent,
+    /// O evento assinado como chegou, para gravar e reenviar.
+    raw: Vec<u8>,
+    /// Os pais e o passado deles, na ordem do log. `None` se a operação não
+    /// se encaixa: um pai que não se encaixa ou um `seq` errado.
+    past: Option<BTreeSet<Key>>,
+}
+
+/// A posição de uma operação no log.
+type Key = (u64, MessageId);
+
+/// Membros e admins num ponto do log.
+#[derive(Debug)]
+struct Roster {
+    members: BTreeSet<NodeId>,
+    admins: BTreeSet<NodeId>,
+}
+
+// endregion:    --- structs
+
+// region:       --- Room impl
+
+impl Room {
+    pub fn load(owner: NodeId, context: RoomContext, me: SigningKey) -> Result<Self> {
+        let mut path = rooms_path();
+        path.push(base58::encode(context.topic.as_bytes()).into_string());
+
+        let mut room = Self::new(owner, path, me);
+
+        if room.path.exists() {
+            let raws = postcard::from_bytes::<Vec<Vec<u8>>>(&fs::read(&room.path)?)?;
+
+            // o carimbo já foi conferido quando a operação chegou
+            let context = RoomContext { work: 0, ..context };
+
+            for raw in raws {
+                // de um formato que não vale mais: os vizinhos reenviam o que ainda vale
+                let Ok(event) = SignedChatEvent::decode(&raw)
+                    .and_then(|unverified_event| unverified_event.verify_into(&context))
+                else {
+                    continue;
+                };
+
+                room.add(raw, event);
+            }
+        }
+
+        room.replay();
+
+        Ok(room)
+    }
+
+    fn new(owner: NodeId, path: PathBuf, me: SigningKey) -> Self {
+        Self {
+            owner,
+            me,
+            path,
+            ops: BTreeMap::new(),
+            seqs: HashMap::new(),
+            valid: BTreeSet::new(),
+            pending: Vec::new(),
+            members: BTreeSet::from([owner]),
+            admins: BTreeSet::from([owner]),
+            epoch: None,
+            keys: BTreeMap::new(),
+        }
+    }
+
+    pub fn owner(&self) -> NodeId {
+        self.owner
+    }
+
+    pub fn is_member(&self, actor: &NodeId) -> bool {
+        self.members.contains(actor)
+    }
+
+    pub fn is_admin(&self, actor: &NodeId) -> bool {
+        self.admins.contains(actor)
+    }
+
+    pub fn members(&self) -> &BTreeSet<NodeId> {
+        &self.members
+    }
+
+    /// A época mais recente, se a sala já tem chave.
+    pub fn epoch(&self) -> Option<u64> {
+        self.epoch
+    }
+
+    /// A chave de uma época, se ela foi selada para nós.
+    pub fn key(&self, epoch: u64) -> Option<GroupKey> {
+        self.keys.get(&epoch).copied()
+    }
+
+    /// O `seq` da próxima operação nossa, citando `heads`. `None` se o log
+    /// chegou no fim dos `u64`.
+    pub fn next_seq(&self) -> Option<u64> {
+        self.valid
+            .iter()
+            .map(|id| self.seqs[id])
+            .max()
+            .map_or(Some(0), |seq| seq.checked_add(1))
+    }
+
+    /// As operações aceitas que nenhuma outra aceita cita, as mais novas
+    /// primeiro: os pais da próxima operação nossa.
+    pub fn heads(&self) -> Vec<MessageId> {
+        let cited = self
+            .valid_ops()
+            .flat_map(|(_, op)| parents(&op.event))
+            .collect::<BTreeSet<_>>();
+        let mut heads = self
+            .valid_ops()
+            .filter(|(id, _)| !cited.contains(id))
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+
+        heads.sort_by_key(|id| std::cmp::Reverse(self.seqs[id]));
+        heads.truncate(MAX_PARENTS);
+
+        heads
+    }
+
+    /// Guarda uma operação, se quem assinou podia fazê-la, e recalcula os
+    /// membros.
+    ///
+    /// Retorna as operações que passaram a valer: esta e as que esperavam por
+    /// ela. Vazio se ela já estava no log, foi recusada ou ainda espera pais
+    /// que não chegaram.
+    pub fn insert(&mut self, raw: Vec<u8>, event: ChatEvent) -> Result<Vec<ChatEvent>> {
+        let mut accepted = Vec::new();
+
+        if !self.try_insert(raw, event.clone()) {
+            return Ok(accepted);
+        }
+
+        accepted.push(event);
+
+        // o que esperava por esta operação pode ter ficado completo
+        while let Some(index) = self
+            .pending
+            .iter()
+            .position(|(_, event)| parents(event).all(|id| self.seqs.contains_key(&id)))
+        {
+            let (raw, event) = self.pending.remove(index);
+
+            if self.try_insert(raw, event.clone()) {
+                accepted.push(event);
+            }
+        }
+
+        self.save()?;
+
+        Ok(accepted)
+    }
+
+    /// Os eventos assinados do log, para mandar a quem acabou de conectar.
+    pub fn raw_ops(&self) -> Vec<Vec<u8>> {
+        self.ops.values().map(|op| op.raw.clone()).collect()
+    }
+
+    /// Só guarda a operação se ela vale; com pais desconhecidos, ela espera
+    /// na memória.
+    fn try_insert(&mut self, raw: Vec<u8>, event: ChatEvent) -> bool {
+        let Some(id) = op_id(&raw, &event) else {
+            return false;
+        };
+
+        if self.seqs.contains_key(&id) {
+            return false;
+        }
+
+        if !parents(&event).all(|parent| self.seqs.contains_key(&parent)) {
+            self.park(raw, event);
+
+            return false;
+        }
+
+        let Some(key) = self.add(raw, event) else {
+            return false;
+        };
+        let op = &self.ops[&key];
+
+        // só a remoção pelo dono muda o que já valia; o resto se decide sozinho
+        if is_owner_removal(self.owner, &op.event) {
+            self.replay();
+        } else if self.is_valid(&key, op, &self.removals()) {
+            self.valid.insert(id);
+            self.refresh();
+        }
+
+        if !self.valid.contains(&id) {
+            // ninguém cita esta operação ainda, e uma que não vale não muda as
+            // outras: tirar do log basta
+            self.remove(id);
+
+            return false;
+        }
+
+        true
+    }
+
+    /// Guarda uma operação que espera pais. Quem assina muito só empurra as
+    /// próprias para fora; com a fila cheia, sai a mais velha de quem tem mais.
+    fn park(&mut self, raw: Vec<u8>, event: ChatEvent) {
+        if self.pending.iter().any(|(pending, _)| *pending == raw) {
+            return;
+        }
+
+        let mut counts = HashMap::<NodeId, usize>::new();
+
+        for (_, pending) in &self.pending {
+            *counts.entry(pending.actor()).or_default() += 1;
+        }
+
+        let actor = event.actor();
+        let evict = if counts.get(&actor).copied().unwrap_or_default() >= MAX_PENDING_PER_AUTHOR {
+            Some(actor)
+        } else if self.pending.len() >= MAX_PENDING {
+            counts
+                .into_iter()
+                .max_by_key(|(_, count)| *count)
+                .map(|(actor, _)| actor)
+        } else {
+            None
+        };
+
+        if let Some(evict) = evict
+            && let Some(index) = self
+                .pending
+                .iter()
+                .position(|(_, pending)| pending.actor() == evict)
+        {
+            self.pending.remove(index);
+        }
+
+        self.pending.push((raw, event));
+    }
+
+    fn add(&mut self, raw: Vec<u8>, event: ChatEvent) -> Option<Key> {
+        let (Some(id), Some(seq)) = (op_id(&raw, &event), seq(&event)) else {
+            return None;
+        };
+        let past = self.past(&event, seq);
+
+        self.seqs.insert(id, seq);
+        self.ops.insert((seq, id), Op { event, raw, past });
+
+        Some((seq, id))
+    }
+
+    /// Os pais de `event` e o passado deles, se todos estão no log e se
+    /// encaixam e se `seq` é um a mais que o maior deles. Os pais não mudam,
+    /// então isso é calculado uma vez, quando a operação entra.
+    fn past(&self, event: &ChatEvent, seq: u64) -> Option<BTreeSet<Key>> {
+        let mut past = BTreeSet::new();
+        let mut expected = 0;
+
+        for parent in parents(event) {
+            let key = (*self.seqs.get(&parent)?, parent);
+
+            past.extend(self.ops[&key].past.as_ref()?);
+            past.insert(key);
+            expected = expected.max(key.0.checked_add(1)?);
+        }
+
+        (expected == seq).then_some(past)
+    }
+
+    fn remove(&mut self, id: MessageId) {
+        if let Some(seq) = self.seqs.remove(&id) {
+            self.ops.remove(&(seq, id));
+        }
+    }
+
+    fn valid_ops(&self) -> impl Iterator<Item = (MessageId, &Op)> {
+        self.ops
+            .iter()
+            .filter(|((_, id), _)| self.valid.contains(id))
+            .map(|((_, id), op)| (*id, op))
+    }
+
+    /// Decide quais operações do log valem e aplica as que valem em ordem.
+    ///
+    /// Uma operação vale se os pais estão no log, se o `seq` é um a mais que
+    /// o maior deles e se quem assinou podia fazê-la no estado do passado
+    /// dela. Qualquer admin adiciona e remove membros e troca a chave, mas só
+    /// o dono promove e remove admins, e ninguém remove o dono. O que um admin
+    /// assinou sem ter visto a própria remoção pelo dono não vale.
+    fn replay(&mut self) {
+        let removals = self.removals();
+
+        self.valid.clear();
+
+        for (key, op) in &self.ops {
+            if self.is_valid(key, op, &removals) {
+                self.valid.insert(key.1);
+            }
+        }
+
+        self.refresh();
+    }
+
+    /// As remoções pelo dono que se encaixam no log: o dono sempre pode
+    /// remover, então dá para saber antes de decidir o resto.
+    fn removals(&self) -> Vec<(NodeId, Key)> {
+        self.ops
+            .iter()
+            .filter(|(_, op)| op.past.is_some() && is_owner_removal(self.owner, &op.event))
+            .filter_map(|(key, op)| match op.event {
+                ChatEvent::RemoveMember { member, .. } => Some((member, *key)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Se `op` vale, dado o que já se decidiu sobre o passado dela.
+    fn is_valid(&self, key: &Key, op: &Op, removals: &[(NodeId, Key)]) -> bool {
+        let Some(past) = &op.past else {
+            return false;
+        };
+        let actor = op.event.actor();
+        let concurrent_removal = removals.iter().any(|(member, removal)| {
+            *member == actor
+                && !past.contains(removal)
+                && !self.ops[removal]
+                    .past
+                    .as_ref()
+                    .is_some_and(|removal_past| removal_past.contains(key))
+        });
+
+        if actor != self.owner && concurrent_removal {
+            return false;
+        }
+
+        let roster = Roster::of(
+            self.owner,
+            past.iter()
+                .filter(|(_, id)| self.valid.contains(id))
+                .map(|key| &self.ops[key].event),
+        );
+
+        roster.allows(self.owner, &op.event)
+    }
+
+    /// Recalcula membros, admins e chaves a partir das operações que valem.
+    fn refresh(&mut self) {
+        let roster = Roster::of(self.owner, self.valid_ops().map(|(_, op)| &op.event));
+
+        self.members = roster.members;
+        self.admins = roster.admins;
+        self.epoch = None;
+        self.keys.clear();
+
+        let room_keys = self
+            .valid_ops()
+            .filter_map(|(_, op)| match &op.event {
+                ChatEvent::RoomKey {
+                    actor, seq, keys, ..
+                } => Some((*seq, group_key::open(keys, actor, &self.me))),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        for (seq, key) in room_keys {
+            self.epoch = Some(seq);
+
+            if let Some(key) = key {
+                self.keys.insert(seq, key);
+            }
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let raws = self.raw_ops();
+
+        fs::write(&self.path, postcard::to_allocvec(&raws)?)?;
+
+        Ok(())
+    }
+}
+
+// endregion:    --- Room impl
+
+// region:       --- Roster impl
+
+impl Roster {
+    /// Aplica `events`, já aceitos e em ordem, a partir da sala só com o dono.
+    fn of<'a>(owner: NodeId, events: impl Iterator<Item = &'a ChatEvent>) -> Self {
+        let mut roster = Self {
+            members: BTreeSet::from([owner]),
+            admins: BTreeSet::from([owner]),
+        };
+
+        for event in events {
+            match *event {
+                ChatEvent::AddMember { member, .. } => {
+                    roster.members.insert(member);
+                }
+                ChatEvent::RemoveMember { member, .. } => {
+                    roster.members.remove(&member);
+                    roster.admins.remove(&member);
+                }
+                ChatEvent::Promote { member, .. } if roster.members.contains(&member) => {
+                    roster.admins.insert(member);
+                }
+                _ => {}
+            }
+        }
+
+        roster
+    }
+
+    fn allows(&self, owner: NodeId, event: &ChatEvent) -> bool {
+        match *event {
+            ChatEvent::AddMember { actor, .. } | ChatEvent::RoomKey { actor, .. } => {
+                self.admins.contains(&actor)
+            }
+            ChatEvent::RemoveMember { actor, member, .. } => {
+                self.admins.contains(&actor)
+                    && member != owner
+                    && (actor == owner || !self.admins.contains(&member))
+            }
+            ChatEvent::Promote { actor, member, .. } => {
+                actor == owner && self.members.contains(&member)
+            }
+            _ => false,
+        }
+    }
+}
+
+// endregion:    --- Roster impl
+
+// region:       --- utils
+
+/// Se o evento é uma operação de sala (`AddMember`, `RemoveMember`, `Promote`,
+/// `RoomKey`).
+pub fn is_op(event: &ChatEvent) -> bool {
+    seq(event).is_some()
+}
+
+fn seq(event: &ChatEvent) -> Option<u64> {
+    match event {
+        ChatEvent::AddMember { seq, .. }
+        | ChatEvent::RemoveMember { seq, .. }
+        | ChatEvent::Promote { seq, .. }
+        | ChatEvent::RoomKey { seq, .. } => Some(*seq),
+        _ => None,
+    }
+}
+
+fn parents(event: &ChatEvent) -> impl Iterator<Item = MessageId> + '_ {
+    let parents = match event {
+        ChatEvent::AddMember { parents, .. }
+        | ChatEvent::RemoveMember { parents, .. }
+        | ChatEvent::Promote { parents, .. }
+        | ChatEvent::RoomKey { parents, .. } => parents.as_slice(),
+        _ => &[],
+    };
+
+    parents.iter().copied()
+}
+
+fn is_owner_removal(owner: NodeId, event: &ChatEvent) -> bool {
+    matches!(event, ChatEvent::RemoveMember { actor, .. } if *actor == owner)
+}
+
+/// O id da operação, o mesmo que `SignedChatEvent::id`.
+fn op_id(raw: &[u8], event: &ChatEvent) -> Option<MessageId> {
+    if !is_op(event) {
+        return None;
+    }
+
+    SignedChatEvent::decode(raw).ok().map(|signed| signed.id())
+}
+
+// endregion:    --- utils
+
+#[cfg(test)]
+mod tests {
+    use iroh_gossip::proto::TopicId;
+
+    use super::*;
+    use crate::chat_event::ChatEventBody;
+
+    struct Peer {
+        key: SigningKey,
+        id: NodeId,
+    }
+
+    fn peer() -> Peer {
+        let key = SigningKey::from_bytes(&rand::random());
+        let id = NodeId::from(key.verifying_key());
+
+        Peer { key, id }
+    }
+
+    fn context() -> RoomContext {
+        RoomContext {
+            topic: TopicId::from_bytes([7; 32]),
+            work: 0,
+            accept_legacy: false,
+        }
+    }
+
+    fn room(owner: &Peer) -> Room {
+        let path = std::env::temp_dir().join(format!("chat-p2p-room-{}", rand::random::<u64>()));
+
+        Room::new(owner.id, path, owner.key.clone())
+    }
+
+    /// Assina a operação como `by` a faria agora, citando as pontas do log.
+    fn op(
+        room: &Room,
+        by: &Peer,
+        body: impl FnOnce(u64, Vec<MessageId>) -> ChatEventBody,
+    ) -> (Vec<u8>, ChatEvent) {
+        at(by, room.next_seq().unwrap(), room.heads(), body)
+    }
+
+    /// Assina a operação com `seq` e pais escolhidos à mão.
+    fn at(
+        by: &Peer,
+        seq: u64,
+        parents: Vec<MessageId>,
+        body: impl FnOnce(u64, Vec<MessageId>) -> ChatEventBody,
+    ) -> (Vec<u8>, ChatEvent) {
+        // sem carimbo, a assinatura nem chega a esperar
+        let (signed, event) = futures_lite::future::block_on(
+            ChatEvent::builder(context())
+                .body(body(seq, parents))
+                .sign_with_echo(&by.key),
+        );
+
+        (signed.to_vec(), event)
+    }
+
+    fn add(member: NodeId) -> impl FnOnce(u64, Vec<MessageId>) -> ChatEventBody {
+        move |seq, parents| ChatEventBody::AddMember {
+            member,
+            seq,
+            parents,
+        }
+    }
+
+    fn remove(member: NodeId) -> impl FnOnce(u64, Vec<MessageId>) -> ChatEventBody {
+        move |seq, parents| ChatEventBody::RemoveMember {
+            member,
+            seq,
+            parents,
+        }
+    }
+
+    fn promote(member: NodeId) -> impl FnOnce(u64, Vec<MessageId>) -> ChatEventBody {
+        move |seq, parents| ChatEventBody::Promote {
+            member,
+            seq,
+            parents,
+        }
+    }
+
+    fn insert(room: &mut Room, (raw, event): (Vec<u8>, ChatEvent)) -> usize {
+        room.insert(raw, event).unwrap().len()
+    }
+
+    fn apply(
+        room: &mut Room,
+        by: &Peer,
+        body: impl FnOnce(u64, Vec<MessageId>) -> ChatEventBody,
+    ) -> usize {
+        let op = op(room, by, body);
+
+        insert(room, op)
+    }
+
+    #[test]
+    fn owner_adds_and_promotes() {
+        let (owner, alice, bob) = (peer(), peer(), peer());
+        let mut room = room(&owner);
+
+        assert_eq!(apply(&mut room, &owner, add(alice.id)), 1);
+        assert_eq!(apply(&mut room, &owner, promote(alice.id)), 1);
+        assert_eq!(apply(&mut room, &alice, add(bob.id)), 1);
+
+        assert!(room.is_admin(&alice.id));
+        assert!(room.is_member(&bob.id));
+        assert_eq!(room.next_seq(), Some(3));
+    }
+
+    #[test]
+    fn members_cannot_issue_ops() {
+        let (owner, alice, mallory) = (peer(), peer(), peer());
+        let mut room = room(&owner);
+
+        apply(&mut room, &owner, add(alice.id));
+
+        assert_eq!(apply(&mut room, &alice, add(mallory.id)), 0);
+        assert!(!room.is_member(&mallory.id));
+        // nem fica no log para ser reenviado
+        assert_eq!(room.raw_ops().len(), 1);
+    }
+
+    #[test]
+    fn seq_is_not_chosen_by_the_signer() {
+        let (owner, alice, bob) = (peer(), peer(), peer());
+        let mut room = room(&owner);
+
+        apply(&mut room, &owner, add(alice.id));
+
+        let heads = room.heads();
+
+        assert_eq!(
+            insert(&mut room, at(&owner, u64::MAX, heads, add(bob.id))),
+            0
+        );
+        assert_eq!(room.next_seq(), Some(1));
+    }
+
+    #[test]
+    fn only_the_owner_removes_admins() {
+        let (owner, alice, bob) = (peer(), peer(), peer());
+        let mut room = room(&owner);
+
+        apply(&mut room, &owner, add(alice.id));
+        apply(&mut room, &owner, add(bob.id));
+        apply(&mut room, &owner, promote(alice.id));
+        apply(&mut room, &owner, promote(bob.id));
+
+        assert_eq!(apply(&mut room, &alice, remove(bob.id)), 0);
+        assert_eq!(apply(&mut room, &alice, remove(owner.id)), 0);
+        assert_eq!(apply(&mut room, &owner, remove(bob.id)), 1);
+        assert!(!room.is_member(&bob.id));
+    }
+
+    #[test]
+    fn removed_admin_cannot_backdate() {
+        let (owner, alice, mallory) = (peer(), peer(), peer());
+        let mut room = room(&owner);
+
+        apply(&mut room, &owner, add(alice.id));
+        apply(&mut room, &owner, promote(alice.id));
+
+        // o que a alice via antes de ser removida
+        let (seq, heads) = (room.next_seq().unwrap(), room.heads());
+
+        apply(&mut room, &owner, remove(alice.id));
+
+        assert_eq!(
+            insert(&mut room, at(&alice, seq, heads, add(mallory.id))),
+            0
+        );
+        assert!(!room.is_member(&mallory.id));
+    }
+
+    #[test]
+    fn removal_cancels_concurrent_ops_of_the_removed_admin() {
+        let (owner, alice, bob) = (peer(), peer(), peer());
+        let mut room = room(&owner);
+
+        apply(&mut room, &owner, add(alice.id));
+        apply(&mut room, &owner, promote(alice.id));
+
+        let removal = op(&room, &owner, remove(alice.id));
+
+        // chega antes da remoção, que foi assinada sem ver esta operação
+        assert_eq!(apply(&mut room, &alice, add(bob.id)), 1);
+        assert!(room.is_member(&bob.id));
+
+        insert(&mut room, removal);
+
+        assert!(!room.is_member(&alice.id));
+        assert!(!room.is_member(&bob.id));
+    }
+
+    #[test]
+    fn ops_wait_for_missing_parents() {
+        let (owner, alice, bob) = (peer(), peer(), peer());
+        let mut source = room(&owner);
+        let first = op(&source, &owner, add(alice.id));
+
+        insert(&mut source, first.clone());
+
+        let second = op(&source, &owner, add(bob.id));
+        let mut room = room(&owner);
+
+        assert_eq!(insert(&mut room, second), 0);
+        assert!(!room.is_member(&bob.id));
+        assert_eq!(insert(&mut room, first), 2);
+        assert!(room.is_member(&alice.id) && room.is_member(&bob.id));
+    }
+
+    #[test]
+    fn one_author_cannot_flush_pending_ops() {
+        let (owner, alice, bob, mallory) = (peer(), peer(), peer(), peer());
+        let mut source = room(&owner);
+        let first = op(&source, &owner, add(alice.id));
+
+        insert(&mut source, first.clone());
+
+        let second = op(&source, &owner, add(bob.id));
+        let mut room = room(&owner);
+
+        insert(&mut room, second);
+
+        // pais que nunca vão chegar
+        for _ in 0..MAX_PENDING {
+            let (raw, _) = at(&mallory, 0, vec![], add(peer().id));
+            let parent = SignedChatEvent::decode(&raw).unwrap().id();
+
+            insert(&mut room, at(&mallory, 1, vec![parent], add(mallory.id)));
+        }
+
+        assert_eq!(insert(&mut room, first), 2);
+        assert!(room.is_member(&bob.id));
+    }
+
+    #[test]
+    fn promoted_admin_ops_wait_for_the_promotion() {
+        let (owner, alice, bob) = (peer(), peer(), peer());
+        let mut source = room(&owner);
+        let first = op(&source, &owner, add(alice.id));
+
+        insert(&mut source, first.clone());
+
+        let promotion = op(&source, &owner, promote(alice.id));
+
+        insert(&mut source, promotion.clone());
+
+        let addition = op(&source, &alice, add(bob.id));
+        let mut room = room(&owner);
+
+        insert(&mut room, first);
+
+        assert_eq!(insert(&mut room, addition), 0);
+        assert_eq!(insert(&mut room, promotion), 2);
+        assert!(room.is_member(&bob.id));
+    }
+}
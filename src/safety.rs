@@ -0,0 +1,33 @@
+use iroh::NodeId;
+
+/// Contexto do `blake3::derive_key`, para o número não servir a outro uso.
+const CONTEXT: &str = "chat-p2p 2025-09 safety number";
+
+/// Quantos grupos de 5 dígitos o número tem.
+const GROUPS: usize = 6;
+
+/// Número de segurança entre dois pares: 30 dígitos em grupos de 5.
+///
+/// É o mesmo dos dois lados, porque os ids entram em ordem. Se alguém colou o
+/// id errado no `-f`, os números lidos por cada um não batem.
+pub fn safety_number(a: NodeId, b: NodeId) -> String {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    let mut ids = [0u8; 64];
+
+    ids[..32].copy_from_slice(first.as_bytes());
+    ids[32..].copy_from_slice(second.as_bytes());
+
+    let hash = blake3::derive_key(CONTEXT, &ids);
+
+    hash.chunks_exact(5)
+        .take(GROUPS)
+        .map(|chunk| {
+            let mut bytes = [0u8; 8];
+
+            bytes[3..].copy_from_slice(chunk);
+
+            format!("{:05}", u64::from_be_bytes(bytes) % 100_000)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
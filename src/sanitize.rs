@@ -0,0 +1,113 @@
+// region:       --- sanitize
+
+/// Para textos de uma linha, como nomes: quebras viram espaço e o resto dos
+/// controles some.
+pub fn line(text: &str) -> String {
+    strip_ansi(text)
+        .chars()
+        .filter_map(|c| match c {
+            '\n' | '\t' => Some(' '),
+            c if is_unsafe(c) => None,
+            c => Some(c),
+        })
+        .collect()
+}
+
+/// Para mensagens: mantém quebras de linha e tabs, tira o resto dos controles.
+///
+/// Antes de imprimir, as quebras passam por `indent`.
+pub fn text(text: &str) -> String {
+    strip_ansi(text)
+        .chars()
+        .filter(|&c| c == '\n' || c == '\t' || !is_unsafe(c))
+        .collect()
+}
+
+/// Recua as linhas depois da primeira. Na coluna 0 só começam as linhas que
+/// nós montamos, com o rótulo de quem escreveu; sem o recuo, uma mensagem com
+/// `\nbob: ...` passaria por uma linha do bob.
+pub fn indent(text: &str) -> String {
+    text.replace('\n', "\n  ")
+}
+
+/// Remove sequências de escape ANSI (CSI, OSC e afins) do texto.
+pub fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                Some('[') => skip_csi(&mut chars),
+                // OSC, DCS e afins terminam em BEL ou ESC \
+                Some(']' | 'P' | 'X' | '^' | '_') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            // CSI de 8 bits
+            '\u{9b}' => skip_csi(&mut chars),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Controles C0/C1 (inclui `\r` e `\x08`) e os que mudam a direção do texto,
+/// com os quais um par apagaria ou reescreveria linhas de outra pessoa.
+pub fn is_unsafe(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{061c}' | '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}'
+        )
+}
+
+// endregion:    --- sanitize
+
+// region:       --- utils
+
+fn skip_csi(chars: &mut impl Iterator<Item = char>) {
+    for c in chars.by_ref() {
+        if ('\x40'..='\x7e').contains(&c) {
+            break;
+        }
+    }
+}
+
+// endregion:    --- utils
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_are_removed() {
+        assert_eq!(strip_ansi("\x1b[2J\x1b[1;31mvermelho\x1b[0m"), "vermelho");
+        assert_eq!(strip_ansi("\x1b]0;título\x07oi"), "oi");
+        assert_eq!(strip_ansi("\x1b]8;;https://x\x1b\\link"), "link");
+        assert_eq!(strip_ansi("\u{9b}31mcsi de 8 bits"), "csi de 8 bits");
+        // um escape cortado no fim não leva nada junto
+        assert_eq!(strip_ansi("oi\x1b["), "oi");
+    }
+
+    #[test]
+    fn controls_and_bidi_are_removed() {
+        assert_eq!(text("oi\rbob: tchau"), "oibob: tchau");
+        assert_eq!(text("apaga\x08\x08"), "apaga");
+        assert_eq!(text("\u{202e}olleh\u{2066}"), "olleh");
+        assert_eq!(line("\u{200f}alice\u{061c}"), "alice");
+    }
+
+    #[test]
+    fn newlines_never_start_a_line_at_column_zero() {
+        assert_eq!(line("alice\nbob\tcarol"), "alice bob carol");
+        assert_eq!(text("oi\nbob: tchau"), "oi\nbob: tchau");
+        assert_eq!(indent(&text("oi\nbob: tchau\n")), "oi\n  bob: tchau\n  ");
+    }
+}
@@ -0,0 +1,676 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, bail};
+use ed25519_dalek::SigningKey;
+use iroh::NodeId;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+
+use crate::chat_event::{
+    ChatEvent, ChatEventBody, ChatEventBuilder, Initial, MAX_NAME_BYTES, MAX_TEXT_BYTES, MessageId,
+    ReceiptKind, SignedChatEvent, Signer,
+};
+use crate::command::Command;
+use crate::config::{Friend, update_friend};
+use crate::direct::Direct;
+use crate::files::{Files, Output};
+use crate::group_key::{self, Outbox};
+use crate::history::History;
+use crate::moderation::Moderation;
+use crate::preview::make_thumbnail;
+use crate::safety::safety_number;
+use crate::sanitize;
+use crate::typing::TYPING_INTERVAL;
+
+/// Estado local de quem está digitando: chave, nome atual e o sender do tópico.
+pub struct Session {
+    sender: Outbox,
+    bus: broadcast::Sender<ChatEvent>,
+    key: SigningKey,
+    actor: NodeId,
+    name: String,
+    files: Arc<Files>,
+    history: Arc<Mutex<History>>,
+    out: Output,
+    last_typing: Option<Instant>,
+    /// Um aviso de digitação ainda está sendo carimbado.
+    typing_pending: Arc<AtomicBool>,
+    receipts: bool,
+    last_read: Option<MessageId>,
+    moderation: Arc<Mutex<Moderation>>,
+    direct: Option<Arc<Direct>>,
+}
+
+impl Session {
+    pub fn new(
+        sender: Outbox,
+        bus: broadcast::Sender<ChatEvent>,
+        key: SigningKey,
+        name: String,
+        files: Arc<Files>,
+        history: Arc<Mutex<History>>,
+        out: Output,
+    ) -> Self {
+        let actor = NodeId::from(key.verifying_key());
+
+        Self {
+            sender,
+            bus,
+            key,
+            actor,
+            name,
+            files,
+            history,
+            out,
+            last_typing: None,
+            typing_pending: Arc::default(),
+            receipts: true,
+            last_read: None,
+            moderation: Arc::default(),
+            direct: None,
+        }
+    }
+
+    /// Usa as listas de bloqueio compartilhadas com o recebimento.
+    pub fn moderation(mut self, moderation: Arc<Mutex<Moderation>>) -> Self {
+        self.moderation = moderation;
+        self
+    }
+
+    /// Liga as mensagens diretas (`/dm`).
+    pub fn direct(mut self, direct: Arc<Direct>) -> Self {
+        self.direct = Some(direct);
+        self
+    }
+
+    /// Liga ou desliga o envio de confirmações de leitura.
+    pub fn receipts(mut self, enabled: bool) -> Self {
+        self.receipts = enabled;
+        self
+    }
+
+    pub fn actor(&self) -> NodeId {
+        self.actor
+    }
+
+    /// Executa um comando, publicando no tópico quando for o caso.
+    ///
+    /// O eco local das mensagens vai para o barramento, como os eventos recebidos.
+    pub async fn execute(&mut self, command: Command) -> Result<()> {
+        self.mark_read();
+
+        // os pares descartariam sem avisar; melhor recusar aqui
+        let too_long = match &command {
+            Command::Send(text)
+            | Command::Reply(_, text)
+            | Command::Edit(_, text)
+            | Command::Direct(_, text) => text.len() > MAX_TEXT_BYTES,
+            Command::Name(name) | Command::Nick(_, name) => name.len() > MAX_NAME_BYTES,
+            _ => false,
+        };
+
+        if too_long {
+            writeln!(self.out.lock().unwrap(), "too long")?;
+
+            return Ok(());
+        }
+
+        if let Command::Send(_) | Command::Reply(..) = command {
+            // quem recebe a mensagem já limpa o status; o próximo aviso pode sair na hora
+            self.last_typing = None;
+        }
+
+        // mudou quem é membro: quem saiu não pode ler o que vem depois
+        let rotates = matches!(command, Command::AddMember(_) | Command::RemoveMember(_));
+        let (event, local) = match command {
+            Command::Send(message) => {
+                let (event, local) = self
+                    .builder()
+                    .new_message(&self.name, message)
+                    .sign_with_echo()
+                    .await;
+
+                (event, Some(local))
+            }
+            Command::Reply(prefix, message) => {
+                let Some(target) = self.history.lock().unwrap().resolve(&prefix) else {
+                    writeln!(self.out.lock().unwrap(), "no message matches #{prefix}")?;
+
+                    return Ok(());
+                };
+                let (event, local) = self
+                    .builder()
+                    .new_message(&self.name, message)
+                    .reply_to(target)
+                    .sign_with_echo()
+                    .await;
+
+                (event, Some(local))
+            }
+            Command::Thread(prefix) => {
+                let history = self.history.lock().unwrap();
+                let mut out = self.out.lock().unwrap();
+                let Some(root) = history.resolve(&prefix) else {
+                    writeln!(out, "no message matches #{prefix}")?;
+
+                    return Ok(());
+                };
+
+                for (depth, entry) in history.thread(root) {
+                    writeln!(out, "{}{}", "  ".repeat(depth), history.render(entry))?;
+                }
+
+                return Ok(());
+            }
+            Command::Seen(prefix) => {
+                let history = self.history.lock().unwrap();
+                let mut out = self.out.lock().unwrap();
+                let Some(entry) = history.resolve(&prefix).and_then(|id| history.get(&id)) else {
+                    writeln!(out, "no message matches #{prefix}")?;
+
+                    return Ok(());
+                };
+                let (delivered, read) = history.receipts(entry);
+                let names = |actors: Vec<NodeId>| {
+                    let mut names = actors
+                        .iter()
+                        .map(|actor| history.display_name(actor))
+                        .collect::<Vec<_>>();
+
+                    names.sort();
+                    names.join(", ")
+                };
+
+                writeln!(out, "{}", history.render(entry))?;
+                writeln!(out, "  read by: {}", names(read))?;
+                writeln!(out, "  delivered to: {}", names(delivered))?;
+
+                return Ok(());
+            }
+            Command::React(prefix, emoji) => {
+                let Some(target) = self.history.lock().unwrap().resolve(&prefix) else {
+                    writeln!(self.out.lock().unwrap(), "no message matches #{prefix}")?;
+
+                    return Ok(());
+                };
+                let (event, local) = self.builder().react(target, emoji).sign_with_echo().await;
+
+                (event, Some(local))
+            }
+            Command::Nick(peer, nickname) => {
+                let nickname = sanitize::line(&nickname);
+                let mut history = self.history.lock().unwrap();
+                let Some(actor) = history.resolve_actor(&peer) else {
+                    writeln!(self.out.lock().unwrap(), "no single peer matches {peer}")?;
+
+                    return Ok(());
+                };
+
+                let update = |friend: &mut Friend| friend.nickname = Some(nickname.clone());
+
+                if !update_friend(actor, update)? {
+                    writeln!(self.out.lock().unwrap(), "{peer} is not a friend")?;
+
+                    return Ok(());
+                }
+
+                history.update_friend(actor, update);
+
+                return Ok(());
+            }
+            Command::Verify(peer, decision) => {
+                let mut history = self.history.lock().unwrap();
+                let mut out = self.out.lock().unwrap();
+                let Some(actor) = history.resolve_actor(&peer) else {
+                    writeln!(out, "no single peer matches {peer}")?;
+
+                    return Ok(());
+                };
+                let name = history.display_name(&actor);
+
+                let Some(verified) = decision else {
+                    writeln!(out, "safety number with {name}:")?;
+                    writeln!(out, "  {}", safety_number(self.actor, actor))?;
+                    writeln!(
+                        out,
+                        "compare it with theirs out of band, then /verify {peer} ok"
+                    )?;
+
+                    return Ok(());
+                };
+                let update = |friend: &mut Friend| friend.verified = verified;
+
+                if !update_friend(actor, update)? {
+                    writeln!(out, "{name} is not a friend")?;
+
+                    return Ok(());
+                }
+
+                history.update_friend(actor, update);
+
+                if verified {
+                    writeln!(out, "{name} is now verified")?;
+                } else {
+                    writeln!(out, "{name} is no longer verified")?;
+                }
+
+                return Ok(());
+            }
+            Command::Block(peer, blocked) => {
+                let history = self.history.lock().unwrap();
+                let mut out = self.out.lock().unwrap();
+                let Some(actor) = history.resolve_actor(&peer) else {
+                    writeln!(out, "no single peer matches {peer}")?;
+
+                    return Ok(());
+                };
+                let name = history.display_name(&actor);
+
+                self.moderation
+                    .lock()
+                    .unwrap()
+                    .set_blocked(actor, blocked)?;
+
+                if blocked {
+                    writeln!(out, "{name} is now blocked")?;
+                } else {
+                    writeln!(out, "{name} is no longer blocked")?;
+                }
+
+                return Ok(());
+            }
+            Command::Mute(peer, muted) => {
+                let history = self.history.lock().unwrap();
+                let mut out = self.out.lock().unwrap();
+                let Some(actor) = history.resolve_actor(&peer) else {
+                    writeln!(out, "no single peer matches {peer}")?;
+
+                    return Ok(());
+                };
+                let name = history.display_name(&actor);
+
+                self.moderation.lock().unwrap().set_muted(actor, muted)?;
+
+                if muted {
+                    writeln!(out, "{name} is now muted")?;
+                } else {
+                    writeln!(out, "{name} is no longer muted")?;
+                }
+
+                return Ok(());
+            }
+            Command::AddMember(_) | Command::RemoveMember(_) | Command::Promote(_) => {
+                let Some((event, local)) = self.room_op(&command).await? else {
+                    return Ok(());
+                };
+
+                (event, Some(local))
+            }
+            Command::Members => {
+                let history = self.history.lock().unwrap();
+                let moderation = self.moderation.lock().unwrap();
+                let mut out = self.out.lock().unwrap();
+                let Some(room) = moderation.room() else {
+                    writeln!(out, "this room has no owner; start it with --owner")?;
+
+                    return Ok(());
+                };
+
+                for member in room.members() {
+                    let role = if *member == room.owner() {
+                        " (owner)"
+                    } else if room.is_admin(member) {
+                        " (admin)"
+                    } else {
+                        ""
+                    };
+
+                    writeln!(out, "{}{role}", history.display_name(member))?;
+                }
+
+                return Ok(());
+            }
+            Command::Stats => {
+                let history = self.history.lock().unwrap();
+                let moderation = self.moderation.lock().unwrap();
+                let mut out = self.out.lock().unwrap();
+                let limiter = moderation.limiter();
+                let stats = limiter.stats();
+
+                writeln!(
+                    out,
+                    "{} received; dropped {} too big, {} invalid, {} blocked, {} over the limit",
+                    stats.received, stats.oversized, stats.invalid, stats.blocked, stats.limited
+                )?;
+
+                for (actor, peer) in limiter.peers() {
+                    let ignored = peer
+                        .muted_for
+                        .map(|left| format!(", ignored for {}s", left.as_secs()))
+                        .unwrap_or_default();
+
+                    writeln!(
+                        out,
+                        "{}: {} received, {} dropped{ignored}",
+                        history.display_name(&actor),
+                        peer.received,
+                        peer.dropped
+                    )?;
+                }
+
+                return Ok(());
+            }
+            Command::Name(name) => {
+                self.history.lock().unwrap().set_name(self.actor, &name);
+                self.name = name;
+
+                let (event, local) = self.builder().set_name(&self.name).sign_with_echo().await;
+
+                (event, Some(local))
+            }
+            Command::Join => (self.builder().node_joined().sign().await, None),
+            Command::Leave => (self.builder().node_left().sign().await, None),
+            Command::SendFile(path) => {
+                let (name, size, hash) = match self.files.import(&path).await {
+                    Ok(imported) => imported,
+                    Err(e) => {
+                        writeln!(self.out.lock().unwrap(), "{}: {e}", path.display())?;
+
+                        return Ok(());
+                    }
+                };
+                let (event, local) = self
+                    .builder()
+                    .file_shared(name, size, hash)
+                    .sign_with_echo()
+                    .await;
+
+                (event, Some(local))
+            }
+            Command::Image(path) => {
+                let (width, height, thumbnail) = match make_thumbnail(&path) {
+                    Ok(thumbnail) => thumbnail,
+                    Err(e) => {
+                        writeln!(self.out.lock().unwrap(), "{}: {e}", path.display())?;
+
+                        return Ok(());
+                    }
+                };
+                let (event, local) = self
+                    .builder()
+                    .image(&self.name, width, height, thumbnail)
+                    .sign_with_echo()
+                    .await;
+
+                (event, Some(local))
+            }
+            Command::Edit(prefix, new_text) => {
+                let Some(target) = self.own_message(&prefix)? else {
+                    return Ok(());
+                };
+                let (event, local) = self.builder().edit(target, new_text).sign_with_echo().await;
+
+                (event, Some(local))
+            }
+            Command::Delete(prefix) => {
+                let Some(target) = self.own_message(&prefix)? else {
+                    return Ok(());
+                };
+                let (event, local) = self.builder().delete(target).sign_with_echo().await;
+
+                (event, Some(local))
+            }
+            Command::Direct(peer, text) => {
+                let actor = self.history.lock().unwrap().resolve_actor(&peer);
+                let (Some(direct), Some(actor)) = (self.direct.clone(), actor) else {
+                    writeln!(self.out.lock().unwrap(), "no single peer matches {peer}")?;
+
+                    return Ok(());
+                };
+                let out = self.out.clone();
+
+                tokio::spawn(async move {
+                    if let Err(e) = direct.send(actor, &text).await {
+                        let _ = writeln!(out.lock().unwrap(), "[dm] {e}");
+                    }
+                });
+
+                return Ok(());
+            }
+            Command::Get(hash) => {
+                let files = self.files.clone();
+                let out = self.out.clone();
+
+                tokio::spawn(async move {
+                    let result = files.fetch(hash, out.clone()).await;
+                    let mut out = out.lock().unwrap();
+                    let _ = match result {
+                        Ok(path) => writeln!(out, "saved {}", path.display()),
+                        Err(e) => writeln!(out, "{hash}: {e}"),
+                    };
+                });
+
+                return Ok(());
+            }
+            Command::Exit => return Ok(()),
+        };
+
+        if !self.sender.broadcast(&event).await? {
+            writeln!(self.out.lock().unwrap(), "waiting for the room key")?;
+
+            return Ok(());
+        }
+
+        if let Some(local) = local {
+            let _ = self.bus.send(local);
+        }
+
+        if rotates {
+            self.rotate_key().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sorteia uma chave nova para a sala e sela para os membros atuais.
+    ///
+    /// Só admins podem; fora de sala com dono não faz nada.
+    pub async fn rotate_key(&self) -> Result<()> {
+        let (seq, parents, keys) = {
+            let moderation = self.moderation.lock().unwrap();
+            let Some(room) = moderation.room().filter(|room| room.is_admin(&self.actor)) else {
+                return Ok(());
+            };
+            let Some(seq) = room.next_seq() else {
+                bail!("the room log is full");
+            };
+            let keys = group_key::seal(room.members(), &self.key, &rand::random());
+
+            (seq, room.heads(), keys)
+        };
+        // o carimbo pode levar um tempo; o recebimento não espera por ele
+        let (event, local) = self
+            .builder()
+            .room_key(seq, parents, keys)
+            .sign_with_echo()
+            .await;
+
+        if !self.insert_op(&event, &local)? {
+            return Ok(());
+        }
+
+        self.sender.broadcast(&event).await?;
+
+        let _ = self.bus.send(local);
+
+        Ok(())
+    }
+
+    /// Avisa o tópico que estamos digitando, no máximo uma vez por `TYPING_INTERVAL`.
+    ///
+    /// O aviso é efêmero: não passa pelo barramento local nem pelo histórico.
+    /// O carimbo é feito fora da digitação, e um aviso novo não sai enquanto o
+    /// anterior ainda está sendo carimbado.
+    pub fn typing(&mut self) {
+        self.mark_read();
+
+        if self
+            .last_typing
+            .is_some_and(|last| last.elapsed() < TYPING_INTERVAL)
+            || self.typing_pending.swap(true, Ordering::AcqRel)
+        {
+            return;
+        }
+
+        self.last_typing = Some(Instant::now());
+
+        let builder = self.builder().typing();
+        let sender = self.sender.clone();
+        let pending = self.typing_pending.clone();
+
+        tokio::spawn(async move {
+            let event = builder.sign().await;
+
+            pending.store(false, Ordering::Release);
+
+            // efêmero: se não saiu, o próximo aviso sai
+            let _ = sender.broadcast(&event).await;
+        });
+    }
+
+    /// Confirma a leitura de tudo que chegou até agora, se houver novidade.
+    ///
+    /// Chamado quando o usuário mexe no terminal: é o sinal de que viu a tela.
+    /// Como o aviso de digitação, é carimbado fora do caminho da entrada.
+    fn mark_read(&mut self) {
+        if !self.receipts {
+            return;
+        }
+
+        let Some(up_to) = self.history.lock().unwrap().latest_from_others(self.actor) else {
+            return;
+        };
+
+        if self.last_read == Some(up_to) {
+            return;
+        }
+
+        self.last_read = Some(up_to);
+
+        let builder = self.builder().receipt(ReceiptKind::Read, up_to);
+        let sender = self.sender.clone();
+
+        tokio::spawn(async move {
+            let event = builder.sign().await;
+
+            // quem recebe guarda a maior marca; a próxima confirmação cobre esta
+            let _ = sender.broadcast(&event).await;
+        });
+    }
+
+    /// Assina uma operação de sala e a aplica no log local antes de publicar.
+    async fn room_op(&self, command: &Command) -> Result<Option<(SignedChatEvent, ChatEvent)>> {
+        let Some(body) = self.room_op_body(command)? else {
+            return Ok(None);
+        };
+        let (event, local) = self.builder().body(body).sign_with_echo().await;
+
+        // só o dono promove e remove admins, e ninguém remove o dono
+        if !self.insert_op(&event, &local)? {
+            writeln!(self.out.lock().unwrap(), "not allowed")?;
+
+            return Ok(None);
+        }
+
+        Ok(Some((event, local)))
+    }
+
+    /// O corpo da operação, citando as pontas do log que vimos até agora.
+    fn room_op_body(&self, command: &Command) -> Result<Option<ChatEventBody>> {
+        let (Command::AddMember(peer) | Command::RemoveMember(peer) | Command::Promote(peer)) =
+            command
+        else {
+            return Ok(None);
+        };
+        let mut out = self.out.lock().unwrap();
+        let Some(member) = self.history.lock().unwrap().resolve_actor(peer) else {
+            writeln!(out, "no single peer matches {peer}")?;
+
+            return Ok(None);
+        };
+        let moderation = self.moderation.lock().unwrap();
+        let Some(room) = moderation.room() else {
+            writeln!(out, "this room has no owner; start it with --owner")?;
+
+            return Ok(None);
+        };
+
+        if !room.is_admin(&self.actor) {
+            writeln!(out, "only admins can change the members")?;
+
+            return Ok(None);
+        }
+
+        let Some(seq) = room.next_seq() else {
+            writeln!(out, "the room log is full")?;
+
+            return Ok(None);
+        };
+        let parents = room.heads();
+        let body = match command {
+            Command::AddMember(_) => ChatEventBody::AddMember {
+                member,
+                seq,
+                parents,
+            },
+            Command::RemoveMember(_) => ChatEventBody::RemoveMember {
+                member,
+                seq,
+                parents,
+            },
+            _ => ChatEventBody::Promote {
+                member,
+                seq,
+                parents,
+            },
+        };
+
+        Ok(Some(body))
+    }
+
+    /// Aplica a nossa operação no log local. Retorna `false` se ela foi
+    /// recusada, ou se a sala sumiu enquanto o carimbo era minerado.
+    fn insert_op(&self, event: &SignedChatEvent, local: &ChatEvent) -> Result<bool> {
+        let mut moderation = self.moderation.lock().unwrap();
+        let Some(room) = moderation.room_mut() else {
+            return Ok(false);
+        };
+
+        Ok(!room.insert(event.to_vec(), local.clone())?.is_empty())
+    }
+
+    /// Um builder já assinando com a nossa chave.
+    fn builder(&self) -> ChatEventBuilder<Initial, Signer> {
+        ChatEvent::builder(self.sender.room()).signer(&self.key)
+    }
+
+    /// Resolve um prefixo de id para uma mensagem nossa que ainda existe.
+    fn own_message(&self, prefix: &str) -> Result<Option<MessageId>> {
+        let history = self.history.lock().unwrap();
+        let found = history
+            .resolve(prefix)
+            .and_then(|id| history.get(&id))
+            .filter(|entry| entry.actor == self.actor && !entry.deleted)
+            .map(|entry| entry.id);
+
+        if found.is_none() {
+            writeln!(
+                self.out.lock().unwrap(),
+                "no message of yours matches #{prefix}"
+            )?;
+        }
+
+        Ok(found)
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use iroh::NodeId;
+use tokio::sync::{broadcast, watch};
+use tokio::time::Instant;
+
+use crate::chat_event::ChatEvent;
+use crate::history::History;
+use crate::moderation::Moderation;
+
+/// Intervalo mínimo entre dois avisos de digitação nossos.
+pub const TYPING_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Depois disso sem novo aviso, o par deixa de aparecer como digitando.
+const TYPING_TIMEOUT: Duration = Duration::from_secs(5);
+
+// region:       --- status
+
+/// Acompanha quem está digitando e publica o texto de status ("X is typing…").
+///
+/// Os avisos não passam pelo histórico; somem sozinhos após `TYPING_TIMEOUT`
+/// ou quando a mensagem da pessoa chega.
+pub fn spawn_status(
+    mut events: broadcast::Receiver<ChatEvent>,
+    history: Arc<Mutex<History>>,
+    moderation: Arc<Mutex<Moderation>>,
+    me: NodeId,
+) -> watch::Receiver<String> {
+    let (tx, rx) = watch::channel(String::new());
+
+    tokio::spawn(async move {
+        let mut typing = HashMap::<NodeId, Instant>::new();
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(ChatEvent::Typing { actor })
+                        if actor != me && !moderation.lock().unwrap().is_muted(&actor) =>
+                    {
+                        typing.insert(actor, Instant::now());
+                    }
+                    Ok(ChatEvent::NewMessage { actor, .. }) => {
+                        typing.remove(&actor);
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = tick.tick() => {
+                    typing.retain(|_, since| since.elapsed() < TYPING_TIMEOUT);
+                }
+            }
+
+            let status = status_line(&typing, &history);
+
+            tx.send_if_modified(|current| {
+                if *current == status {
+                    return false;
+                }
+
+                *current = status;
+                true
+            });
+        }
+    });
+
+    rx
+}
+
+fn status_line(typing: &HashMap<NodeId, Instant>, history: &Mutex<History>) -> String {
+    let history = history.lock().unwrap();
+    let mut names = typing
+        .keys()
+        .map(|actor| history.display_name(actor))
+        .collect::<Vec<_>>();
+
+    names.sort();
+
+    match names.as_slice() {
+        [] => String::new(),
+        [name] => format!("{name} is typing…"),
+        names => format!("{} are typing…", names.join(", ")),
+    }
+}
+
+// endregion:    --- status
+
+// region:       --- input
+
+/// O que o `WatchInput` devolve: a linha pronta ou só um sinal de edição.
+pub enum Input<T> {
+    Done(T),
+    Edited,
+}
+
+/// Envolve o `readline` para saber quando o usuário mexe na linha.
+///
+/// O `rustyline_async` não expõe as teclas, mas o future dele só acorda por
+/// tecla ou por saída escrita num `SharedWriter`. Toda a nossa saída passa
+/// por `TermWriter`, que conta os bytes; se o future acordou sem saída nova,
+/// foi tecla.
+pub struct WatchInput<'a, F> {
+    inner: Pin<Box<F>>,
+    written: &'a AtomicU64,
+    seen: u64,
+    polled: bool,
+}
+
+impl<'a, F: Future> WatchInput<'a, F> {
+    pub fn new(inner: F, written: &'a AtomicU64) -> Self {
+        Self {
+            inner: Box::pin(inner),
+            seen: written.load(Ordering::SeqCst),
+            written,
+            polled: false,
+        }
+    }
+}
+
+impl<F: Future> Future for WatchInput<'_, F> {
+    type Output = Input<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let written = self.written.load(Ordering::SeqCst);
+        let by_output = written != self.seen;
+
+        self.seen = written;
+
+        if let Poll::Ready(output) = self.inner.as_mut().poll(cx) {
+            return Poll::Ready(Input::Done(output));
+        }
+        if self.polled && !by_output {
+            return Poll::Ready(Input::Edited);
+        }
+
+        self.polled = true;
+
+        Poll::Pending
+    }
+}
+
+/// Um `Write` que conta o que passa por ele, para o `WatchInput`.
+#[derive(Clone)]
+pub struct TermWriter<W> {
+    inner: W,
+    written: Arc<AtomicU64>,
+}
+
+impl<W: Write> TermWriter<W> {
+    pub fn new(inner: W, written: Arc<AtomicU64>) -> Self {
+        Self { inner, written }
+    }
+}
+
+impl<W: Write> Write for TermWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.fetch_add(buf.len() as u64, Ordering::SeqCst);
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// endregion:    --- input